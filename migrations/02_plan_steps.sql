@@ -0,0 +1,18 @@
+-- Extra plan steps contributed at specific phases
+CREATE TABLE plan_steps (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    framework_id TEXT,                -- NULL means applies to all frameworks
+    feature_id TEXT,                  -- NULL means always, regardless of features
+    name TEXT NOT NULL,               -- step id in the plan, e.g. "tailwind-init"
+    phase TEXT NOT NULL,              -- "pre_scaffold", "post_install", ...
+    action TEXT NOT NULL,             -- JSON step action, e.g. {"type": "run_command", ...}
+    FOREIGN KEY (framework_id) REFERENCES frameworks(id),
+    FOREIGN KEY (feature_id) REFERENCES features(id)
+);
+
+-- Generate tailwind.config.js and postcss.config.js once tailwindcss is installed
+INSERT INTO plan_steps (framework_id, feature_id, name, phase, action) VALUES
+    ('react', 'tailwind', 'tailwind-init', 'post_install',
+     '{"type": "run_command", "command": "npx", "args": ["tailwindcss", "init", "-p"], "creates": ["postcss.config.js", "tailwind.config.js"]}'),
+    ('react-ts', 'tailwind', 'tailwind-init', 'post_install',
+     '{"type": "run_command", "command": "npx", "args": ["tailwindcss", "init", "-p"], "creates": ["postcss.config.js", "tailwind.config.js"]}');
@@ -0,0 +1,6 @@
+-- Checks run after everything else has been applied ("verify" phase)
+INSERT INTO plan_steps (framework_id, feature_id, name, phase, action) VALUES
+    (NULL, 'typescript', 'typecheck', 'verify',
+     '{"type": "run_command", "command": "npx", "args": ["tsc", "--noEmit"]}'),
+    (NULL, NULL, 'build', 'verify',
+     '{"type": "run_command", "command": "npm", "args": ["run", "build"]}');
@@ -0,0 +1,12 @@
+-- Commands run at points of the apply lifecycle, e.g.
+--   (NULL, 'eslint', 'husky-install', 'after_install', '{"command": "npx", "args": ["husky", "install"]}')
+CREATE TABLE hooks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    framework_id TEXT,                -- NULL means applies to all frameworks
+    feature_id TEXT,                  -- NULL means always, regardless of features
+    name TEXT NOT NULL,               -- step id in the plan
+    hook_point TEXT NOT NULL,         -- "before_scaffold", "after_install", "after_files" or "after_apply"
+    command TEXT NOT NULL,            -- JSON command, e.g. {"command": "npx", "args": [...]}
+    FOREIGN KEY (framework_id) REFERENCES frameworks(id),
+    FOREIGN KEY (feature_id) REFERENCES features(id)
+);
@@ -0,0 +1,16 @@
+-- Patterns written to .gitignore when the spec asks for a git repository
+CREATE TABLE gitignore_patterns (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    framework_id TEXT,                -- NULL means applies to all frameworks
+    feature_id TEXT,                  -- NULL means always, regardless of features
+    pattern TEXT NOT NULL,
+    FOREIGN KEY (framework_id) REFERENCES frameworks(id),
+    FOREIGN KEY (feature_id) REFERENCES features(id)
+);
+
+INSERT INTO gitignore_patterns (framework_id, feature_id, pattern) VALUES
+    (NULL, NULL, 'node_modules/'),
+    (NULL, NULL, 'dist/'),
+    (NULL, NULL, '.orchestrator/logs/'),
+    (NULL, 'typescript', '*.tsbuildinfo'),
+    (NULL, 'eslint', '.eslintcache');
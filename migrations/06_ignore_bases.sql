@@ -0,0 +1,4 @@
+-- The merge bases under .orchestrator/base/ duplicate the file_writes of the plan in
+-- orchestrator.lock, which stands in for them in a fresh clone. Keep them out of git.
+INSERT INTO gitignore_patterns (framework_id, feature_id, pattern) VALUES
+    (NULL, NULL, '.orchestrator/base/');
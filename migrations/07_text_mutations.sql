@@ -0,0 +1,13 @@
+-- The tailwind import was appended after `export default`, leaving an import at the end of
+-- the file that nothing used. Put it at the top instead and register the plugin in the
+-- `plugins` array; both mutations do nothing once their change is in the file.
+UPDATE config_mutations
+SET mutation_type = 'text_prepend',
+    content = 'import tailwindcss from "tailwindcss";' || char(10)
+WHERE feature_id = 'tailwind' AND file_path IN ('vite.config.js', 'vite.config.ts');
+
+INSERT INTO config_mutations (framework_id, feature_id, file_path, mutation_type, content) VALUES
+    ('react', 'tailwind', 'vite.config.js', 'replace',
+     '{"pattern": "(plugins: \\[react\\(\\))(, tailwindcss\\(\\))?", "with": "$1, tailwindcss()"}'),
+    ('react-ts', 'tailwind', 'vite.config.ts', 'replace',
+     '{"pattern": "(plugins: \\[react\\(\\))(, tailwindcss\\(\\))?", "with": "$1, tailwindcss()"}');
@@ -0,0 +1,10 @@
+-- Add the tailwind import and plugin entry to vite.config through its syntax tree, as one
+-- mutation in place of the text_prepend and replace pair.
+DELETE FROM config_mutations
+WHERE feature_id = 'tailwind' AND file_path IN ('vite.config.js', 'vite.config.ts')
+  AND mutation_type = 'replace';
+
+UPDATE config_mutations
+SET mutation_type = 'js_ast',
+    content = '{"imports": ["import tailwindcss from \"tailwindcss\""], "insert": [{"path": "default.plugins", "value": "tailwindcss()"}]}'
+WHERE feature_id = 'tailwind' AND file_path IN ('vite.config.js', 'vite.config.ts');
@@ -0,0 +1,5 @@
+-- env_set may generate secrets into these files, so they must never be committed
+INSERT INTO gitignore_patterns (framework_id, feature_id, pattern) VALUES
+    (NULL, NULL, '.env'),
+    (NULL, NULL, '.env.local'),
+    (NULL, NULL, '.env.*.local');
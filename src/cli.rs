@@ -1,10 +1,21 @@
+use crate::detect::detect_spec;
 use crate::engine::Engine;
-use crate::executor::Executor;
+use crate::executor::{Applied, ExecuteOptions, Executor};
+use crate::explain::explain;
+use crate::logging::{Event, OutputFormat, Reporter, Verbosity};
+use crate::manifest::{generated_paths, LockManifest};
+use crate::plan::{ExecutionPlan, StepAction};
+use crate::policy::{Confirmation, ExecutionPolicy};
 use crate::registry::sqlite::SqliteRegistry;
+use crate::registry::Registry;
+use crate::revert;
+use crate::sandbox::Sandbox;
 use crate::spec::ProjectSpec;
+use crate::status;
+use crate::wizard::{InitOptions, Wizard};
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use dirs;
+use clap::{Args, Parser, Subcommand};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -12,10 +23,37 @@ use std::path::PathBuf;
 pub struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Print the output of every command as it runs
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Only print errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Interactively create a spec file from the registry
+    Init {
+        /// Output file for the spec (default: spec.json in current dir)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Language id (skips the prompt)
+        #[arg(long)]
+        language: Option<String>,
+        /// Framework id (skips the prompt)
+        #[arg(long)]
+        framework: Option<String>,
+        /// Comma-separated feature ids (skips the prompt)
+        #[arg(long, value_delimiter = ',')]
+        features: Option<Vec<String>>,
+        /// Never prompt; fail if --language or --framework is missing
+        #[arg(long)]
+        non_interactive: bool,
+        /// Overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
     /// Generate an execution plan from a spec file
     Plan {
         /// Path to the project spec JSON file
@@ -23,6 +61,17 @@ enum Commands {
         /// Output file for the plan (default: plan.json in current dir)
         #[arg(short, long)]
         output: Option<PathBuf>,
+        /// Terminal output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Show why each plan item exists
+    Explain {
+        /// Path to the project spec JSON file
+        spec: PathBuf,
+        /// Only explain this package name or file path
+        #[arg(long)]
+        item: Option<String>,
     },
     /// Execute a plan (or a spec directly) in a target directory
     Apply {
@@ -34,7 +83,97 @@ enum Commands {
         /// If input is a spec, resolve and execute directly
         #[arg(long)]
         from_spec: bool,
+        /// Apply a plan whose plan_hash is missing or does not match (e.g. hand-written)
+        #[arg(long)]
+        skip_hash_check: bool,
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
+    /// Add features to an existing project without scaffolding it again
+    Add {
+        /// Feature ids to add
+        #[arg(required = true)]
+        features: Vec<String>,
+        /// Project directory (defaults to current directory)
+        #[arg(short, long)]
+        target: Option<PathBuf>,
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
+    /// Report how a project has drifted from its orchestrator.lock
+    Status {
+        /// Project directory (defaults to current directory)
+        #[arg(short, long)]
+        target: Option<PathBuf>,
+        /// Terminal output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// Bring a project up to date with the current registry
+    Upgrade {
+        /// Project directory (defaults to current directory)
+        #[arg(short, long)]
+        target: Option<PathBuf>,
+        #[command(flatten)]
+        exec: ExecArgs,
     },
+    /// Take features out of a project, undoing what they applied
+    Remove {
+        /// Feature ids to remove
+        #[arg(required = true)]
+        features: Vec<String>,
+        /// Project directory (defaults to current directory)
+        #[arg(short, long)]
+        target: Option<PathBuf>,
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
+}
+
+/// Options shared by every command that executes a plan.
+#[derive(Args)]
+struct ExecArgs {
+    /// Run commands that need confirmation without asking (for CI)
+    #[arg(short, long)]
+    yes: bool,
+    /// Additionally allow this binary to be run (repeatable)
+    #[arg(long = "allow", value_name = "BINARY")]
+    allow: Vec<String>,
+    /// Terminal output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Run up to N independent commands at once
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+    /// Do not run the registry's verification checks afterwards
+    #[arg(long)]
+    skip_verify: bool,
+}
+
+impl ExecArgs {
+    fn policy(&self, registry: &dyn Registry) -> Result<ExecutionPolicy> {
+        let mut policy = ExecutionPolicy::new(if self.yes {
+            Confirmation::AssumeYes
+        } else {
+            Confirmation::Prompt
+        });
+        for binary in &self.allow {
+            policy.allow_binary(binary);
+        }
+        for argv in registry.trusted_commands()? {
+            policy.trust_argv(argv);
+        }
+        Ok(policy)
+    }
+
+    fn options<'a>(&self) -> ExecuteOptions<'a> {
+        ExecuteOptions {
+            jobs: self.jobs as usize,
+            skip_verify: self.skip_verify,
+            merge_bases: Default::default(),
+            before_commit: None,
+        }
+    }
 }
 
 impl Cli {
@@ -49,33 +188,453 @@ impl Cli {
         let db_path = config_dir.join("registry.db");
 
         let registry = SqliteRegistry::new(&db_path)?;
+        let verbosity = if cli.quiet {
+            Verbosity::Quiet
+        } else if cli.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        };
 
         match cli.command {
-            Commands::Plan { spec, output } => {
+            Commands::Init {
+                output,
+                language,
+                framework,
+                features,
+                non_interactive,
+                force,
+            } => {
+                let out_path = output.unwrap_or_else(|| PathBuf::from("spec.json"));
+                if out_path.exists() && !force {
+                    anyhow::bail!(
+                        "{} already exists (use --force to overwrite)",
+                        out_path.display()
+                    );
+                }
+                let opts = InitOptions {
+                    language,
+                    framework,
+                    features,
+                    interactive: !non_interactive,
+                };
+                let stdin = std::io::stdin();
+                let spec =
+                    Wizard::run(&registry, &opts, &mut stdin.lock(), &mut std::io::stdout())?;
+                // Make sure the result actually resolves before writing it
+                Engine::resolve(&spec, &registry)?;
+                spec.to_json_file(&out_path)?;
+                if verbosity >= Verbosity::Normal {
+                    println!("Spec written to {:?}", out_path);
+                }
+            }
+            Commands::Plan {
+                spec,
+                output,
+                format,
+            } => {
+                let reporter = Reporter::new(verbosity, format);
+                let out_path = output.unwrap_or_else(|| PathBuf::from("plan.json"));
+                let result = (|| -> Result<()> {
+                    let spec = ProjectSpec::from_json_file(&spec)?;
+                    let plan =
+                        reporter.step("resolve", None, || Engine::resolve(&spec, &registry))?;
+                    let plan_json = serde_json::to_string_pretty(&plan)?;
+                    std::fs::write(&out_path, plan_json)?;
+                    Ok(())
+                })();
+                report_error(&reporter, &result);
+                result?;
+                if reporter.format() == OutputFormat::Json {
+                    reporter.emit(&Event::FileWritten {
+                        path: &out_path.to_string_lossy(),
+                    });
+                } else if reporter.prints_text() {
+                    println!("Plan written to {:?}", out_path);
+                }
+            }
+            Commands::Explain { spec, item } => {
                 let spec = ProjectSpec::from_json_file(&spec)?;
                 let plan = Engine::resolve(&spec, &registry)?;
-                let out_path = output.unwrap_or_else(|| PathBuf::from("plan.json"));
-                let plan_json = serde_json::to_string_pretty(&plan)?;
-                std::fs::write(out_path, plan_json)?;
-                println!("Plan written to {:?}", out_path);
+                print!("{}", explain(&plan, item.as_deref())?);
+            }
+            Commands::Apply {
+                input,
+                target,
+                from_spec,
+                skip_hash_check,
+                exec,
+            } => {
+                let target_dir = target.unwrap_or_else(|| PathBuf::from("."));
+                let mut policy = exec.policy(&registry)?;
+                let reporter = Reporter::new(verbosity, exec.format);
+                let result = (|| -> Result<(&str, Vec<String>)> {
+                    if from_spec {
+                        // Interpret input as a spec file
+                        let spec = ProjectSpec::from_json_file(&input)?;
+                        let plan = Engine::resolve(&spec, &registry)?;
+                        trust_resolved(&mut policy, &plan, &spec);
+                        let save_lock = |applied: &Applied| {
+                            let mut lock = LockManifest::new(
+                                spec.clone(),
+                                plan.clone(),
+                                applied.changes.clone(),
+                            );
+                            lock.record_files(
+                                &target_dir,
+                                merged_cleanly(
+                                    generated_paths(&plan).iter().map(String::as_str),
+                                    &applied.conflicts,
+                                ),
+                            )?;
+                            lock.save(&target_dir)
+                        };
+                        let mut options = exec.options();
+                        options.before_commit = Some(&save_lock);
+                        let applied =
+                            Executor::execute(&plan, &target_dir, &policy, &reporter, &options)?;
+                        Ok(("Project generated in", applied.conflicts))
+                    } else {
+                        // Interpret input as a plan file
+                        let plan = ExecutionPlan::from_json_file(&input)?;
+                        if !skip_hash_check {
+                            plan.verify_hash()?;
+                        }
+                        let save_lock = |applied: &Applied| {
+                            let mut lock =
+                                LockManifest::for_plan(plan.clone(), applied.changes.clone());
+                            lock.record_files(
+                                &target_dir,
+                                merged_cleanly(
+                                    generated_paths(&plan).iter().map(String::as_str),
+                                    &applied.conflicts,
+                                ),
+                            )?;
+                            lock.save(&target_dir)
+                        };
+                        let mut options = exec.options();
+                        options.before_commit = Some(&save_lock);
+                        let applied =
+                            Executor::execute(&plan, &target_dir, &policy, &reporter, &options)?;
+                        Ok(("Plan executed in", applied.conflicts))
+                    }
+                })();
+                let (done, conflicts) = finish(&reporter, result)?;
+                unresolved(&target_dir, &conflicts)?;
+                if reporter.prints_text() {
+                    println!("{} {}", done, target_dir.display());
+                }
+            }
+            Commands::Add {
+                features,
+                target,
+                exec,
+            } => {
+                let target_dir = target.unwrap_or_else(|| PathBuf::from("."));
+                let mut policy = exec.policy(&registry)?;
+                let reporter = Reporter::new(verbosity, exec.format);
+                let result = (|| -> Result<Vec<String>> {
+                    let lock = match LockManifest::load(&target_dir)? {
+                        Some(lock) => lock,
+                        None => {
+                            let spec = detect_spec(&target_dir, &registry)?;
+                            let plan = Engine::resolve(&spec, &registry)?;
+                            LockManifest::new(spec, plan, Vec::new())
+                        }
+                    };
+                    let mut spec = match &lock.spec {
+                        Some(spec) => spec.clone(),
+                        None => detect_spec(&target_dir, &registry)?,
+                    };
+                    for feature in &features {
+                        if spec.features.contains(feature) {
+                            anyhow::bail!("Feature '{}' is already part of the project", feature);
+                        }
+                        spec.features.push(feature.clone());
+                    }
+                    let old = baseline_plan(&lock, &registry)?;
+                    let plan = Engine::resolve_delta(&old, &spec, &registry)?;
+                    trust_resolved(&mut policy, &plan, &spec);
+                    let full = Engine::resolve(&spec, &registry)?;
+                    let save_lock = |applied: &Applied| {
+                        let mut lock = lock.clone();
+                        lock.applied.extend(applied.changes.iter().cloned());
+                        lock.record_files(
+                            &target_dir,
+                            merged_cleanly(
+                                generated_paths(&plan).iter().map(String::as_str),
+                                &applied.conflicts,
+                            ),
+                        )?;
+                        lock.advance(spec.clone(), full.clone());
+                        lock.save(&target_dir)
+                    };
+                    let mut options = exec.options();
+                    options.merge_bases = merge_bases(&old);
+                    options.before_commit = Some(&save_lock);
+                    let applied =
+                        Executor::execute(&plan, &target_dir, &policy, &reporter, &options)?;
+                    Ok(applied.conflicts)
+                })();
+                let conflicts = finish(&reporter, result)?;
+                unresolved(&target_dir, &conflicts)?;
+                if reporter.prints_text() {
+                    println!("Added {} to {}", features.join(", "), target_dir.display());
+                }
             }
-            Commands::Apply { input, target, from_spec } => {
+            Commands::Status { target, format } => {
                 let target_dir = target.unwrap_or_else(|| PathBuf::from("."));
-                if from_spec {
-                    // Interpret input as a spec file
-                    let spec = ProjectSpec::from_json_file(&input)?;
-                    let plan = Engine::resolve(&spec, &registry)?;
-                    Executor::execute(&plan, &target_dir)?;
-                    println!("Project generated in {}", target_dir.display());
+                let lock = match LockManifest::load(&target_dir)? {
+                    Some(lock) => lock,
+                    None => anyhow::bail!("{} has no orchestrator.lock", target_dir.display()),
+                };
+                let drift = status::check(&target_dir, &lock, &baseline_plan(&lock, &registry)?)?;
+                if format == OutputFormat::Json {
+                    println!("{}", serde_json::to_string(&drift)?);
+                } else if drift.is_empty() {
+                    if verbosity >= Verbosity::Normal {
+                        println!("{} matches orchestrator.lock", target_dir.display());
+                    }
                 } else {
-                    // Interpret input as a plan file
-                    let plan_json = std::fs::read_to_string(&input)?;
-                    let plan: crate::plan::ExecutionPlan = serde_json::from_str(&plan_json)?;
-                    Executor::execute(&plan, &target_dir)?;
-                    println!("Plan executed in {}", target_dir.display());
+                    for d in &drift {
+                        println!("{}", d.describe());
+                    }
+                }
+                if !drift.is_empty() {
+                    anyhow::bail!("{} difference(s) from orchestrator.lock", drift.len());
+                }
+            }
+            Commands::Upgrade { target, exec } => {
+                let target_dir = target.unwrap_or_else(|| PathBuf::from("."));
+                let mut policy = exec.policy(&registry)?;
+                let reporter = Reporter::new(verbosity, exec.format);
+                let result = (|| -> Result<Option<Vec<String>>> {
+                    let mut lock = match LockManifest::load(&target_dir)? {
+                        Some(lock) => lock,
+                        None => anyhow::bail!(
+                            "{} has no orchestrator.lock; upgrade needs the plan it was generated from",
+                            target_dir.display()
+                        ),
+                    };
+                    let spec = lock.spec()?.clone();
+                    let old = baseline_plan(&lock, &registry)?;
+                    let new = Engine::resolve(&spec, &registry)?;
+                    if new.plan_hash == old.plan_hash {
+                        return Ok(None);
+                    }
+
+                    // Changed mutations and files the new plan drops are undone first; files
+                    // it writes again are merged with the user's edits instead
+                    let mut stale = Engine::resolve_removal(&old, &spec, &registry)?;
+                    stale
+                        .file_writes
+                        .retain(|w| !new.file_writes.iter().any(|n| n.path == w.path));
+                    let sandbox = Sandbox::new(&target_dir)?;
+                    reporter.step("revert", None, || {
+                        revert::undo(&mut lock.applied, &stale, &sandbox, &reporter)
+                    })?;
+                    // The reverted changes are gone even if a later step fails
+                    lock.save(&target_dir)?;
+
+                    let delta = Engine::resolve_delta(&old, &spec, &registry)?;
+                    trust_resolved(&mut policy, &delta, &spec);
+                    let save_lock = |applied: &Applied| {
+                        let mut lock = lock.clone();
+                        lock.applied.extend(applied.changes.iter().cloned());
+                        // Conflicted files keep their old hash so `status` shows them as edited
+                        let generated = generated_paths(&delta);
+                        let touched = generated
+                            .iter()
+                            .map(String::as_str)
+                            .chain(stale.modifications.iter().map(|m| m.path.as_str()))
+                            .chain(stale.file_writes.iter().map(|w| w.path.as_str()));
+                        lock.record_files(
+                            &target_dir,
+                            merged_cleanly(touched, &applied.conflicts),
+                        )?;
+                        lock.advance(spec.clone(), new.clone());
+                        lock.save(&target_dir)
+                    };
+                    let mut options = exec.options();
+                    options.merge_bases = merge_bases(&old);
+                    options.before_commit = Some(&save_lock);
+                    let applied =
+                        Executor::execute(&delta, &target_dir, &policy, &reporter, &options)?;
+                    Ok(Some(applied.conflicts))
+                })();
+                let conflicts = finish(&reporter, result)?;
+                match conflicts {
+                    None => {
+                        if reporter.prints_text() {
+                            println!("{} is already up to date", target_dir.display());
+                        }
+                    }
+                    Some(conflicts) => {
+                        unresolved(&target_dir, &conflicts)?;
+                        if reporter.prints_text() {
+                            println!("Upgraded {}", target_dir.display());
+                        }
+                    }
+                }
+            }
+            Commands::Remove {
+                features,
+                target,
+                exec,
+            } => {
+                let target_dir = target.unwrap_or_else(|| PathBuf::from("."));
+                let mut policy = exec.policy(&registry)?;
+                let reporter = Reporter::new(verbosity, exec.format);
+                let result = (|| -> Result<()> {
+                    let mut lock = match LockManifest::load(&target_dir)? {
+                        Some(lock) => lock,
+                        None => anyhow::bail!(
+                            "{} has no orchestrator.lock; remove needs the record of what was applied",
+                            target_dir.display()
+                        ),
+                    };
+                    let required: Vec<String> = registry
+                        .features_for_framework(&lock.spec()?.framework)?
+                        .into_iter()
+                        .filter(|f| f.required)
+                        .map(|f| f.id)
+                        .collect();
+                    let mut spec = lock.spec()?.clone();
+                    for feature in &features {
+                        if !spec.features.contains(feature) {
+                            anyhow::bail!("Feature '{}' is not part of the project", feature);
+                        }
+                        if required.contains(feature) {
+                            anyhow::bail!(
+                                "Feature '{}' is required by framework '{}'",
+                                feature,
+                                spec.framework
+                            );
+                        }
+                        spec.features.retain(|f| f != feature);
+                    }
+
+                    let removal = Engine::resolve_removal(
+                        &baseline_plan(&lock, &registry)?,
+                        &spec,
+                        &registry,
+                    )?;
+                    let sandbox = Sandbox::new(&target_dir)?;
+                    reporter.step("revert", None, || {
+                        revert::undo(&mut lock.applied, &removal, &sandbox, &reporter)
+                    })?;
+                    let reverted = removal
+                        .modifications
+                        .iter()
+                        .map(|m| m.path.as_str())
+                        .chain(removal.file_writes.iter().map(|w| w.path.as_str()));
+                    lock.record_files(&target_dir, reverted)?;
+                    // The reverted changes are gone even if a later step fails
+                    lock.save(&target_dir)?;
+                    trust_resolved(&mut policy, &removal.plan, &spec);
+                    Executor::execute(
+                        &removal.plan,
+                        &target_dir,
+                        &policy,
+                        &reporter,
+                        &exec.options(),
+                    )?;
+                    lock.advance(spec.clone(), Engine::resolve(&spec, &registry)?);
+                    lock.save(&target_dir)?;
+                    Ok(())
+                })();
+                finish(&reporter, result)?;
+                if reporter.prints_text() {
+                    println!(
+                        "Removed {} from {}",
+                        features.join(", "),
+                        target_dir.display()
+                    );
                 }
             }
         }
         Ok(())
     }
 }
+
+/// The full plan the project was last brought to. Version 1 locks did not keep it, so it
+/// is resolved again from their spec.
+fn baseline_plan(lock: &LockManifest, registry: &dyn Registry) -> Result<ExecutionPlan> {
+    match &lock.plan {
+        Some(plan) => Ok(plan.clone()),
+        None => Engine::resolve(lock.spec()?, registry),
+    }
+}
+
+/// The content `plan` generated each file from, standing in for `.orchestrator/base/`,
+/// which is not committed and so missing from a fresh clone.
+fn merge_bases(plan: &ExecutionPlan) -> BTreeMap<String, String> {
+    plan.effective_steps()
+        .into_iter()
+        .filter_map(|step| match step.action {
+            StepAction::WriteFile { path, content } => Some((path, content)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Leave files with conflict markers out of the lock's hashes, so `status` keeps
+/// reporting them as edited until they are resolved.
+fn merged_cleanly<'a>(
+    paths: impl IntoIterator<Item = &'a str>,
+    conflicts: &'a [String],
+) -> impl Iterator<Item = &'a str> {
+    paths
+        .into_iter()
+        .filter(move |path| !conflicts.iter().any(|c| c == path))
+}
+
+/// Fail a run that left conflict markers behind, naming the files.
+fn unresolved(target_dir: &std::path::Path, conflicts: &[String]) -> Result<()> {
+    if !conflicts.is_empty() {
+        anyhow::bail!(
+            "Merging into {} left conflicts in {}; resolve the marked sections by hand",
+            target_dir.display(),
+            conflicts.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// A plan just resolved from the local registry is trusted, except hooks the spec
+/// brought along.
+fn trust_resolved(policy: &mut ExecutionPolicy, plan: &ExecutionPlan, spec: &ProjectSpec) {
+    let spec_hooks: Vec<Vec<String>> = spec
+        .hooks
+        .values()
+        .flatten()
+        .map(|command| command.argv())
+        .collect();
+    for argv in Executor::commands(plan) {
+        if !spec_hooks.contains(&argv) {
+            policy.trust_argv(argv);
+        }
+    }
+}
+
+/// Report the outcome of an executing command and point at its log.
+fn finish<T>(reporter: &Reporter, result: Result<T>) -> Result<T> {
+    report_error(reporter, &result);
+    if let Some(path) = reporter.path() {
+        if result.is_err() || reporter.prints_text() {
+            eprintln!("Log written to {}", path.display());
+        }
+    }
+    result
+}
+
+/// In JSON mode, failures are also reported as an `error` event on stdout.
+fn report_error<T>(reporter: &Reporter, result: &Result<T>) {
+    if let Err(e) = result {
+        if reporter.format() == OutputFormat::Json {
+            reporter.emit(&Event::Error {
+                message: &format!("{:#}", e),
+            });
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::registry::Registry;
+use crate::spec::{ProjectSpec, CURRENT_SPEC_VERSION};
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::path::Path;
+
+/// Guess the spec of a project that has no lock manifest from its `package.json`.
+///
+/// The framework comes from the packages it depends on; a feature counts as present when
+/// every dependency the registry lists for it is already declared.
+pub fn detect_spec(project_dir: &Path, registry: &dyn Registry) -> Result<ProjectSpec> {
+    let path = project_dir.join("package.json");
+    let contents = std::fs::read_to_string(&path).with_context(|| {
+        format!(
+            "{} has no orchestrator.lock and no package.json to detect the framework from",
+            project_dir.display()
+        )
+    })?;
+    let package: Value = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let framework_id = match detect_framework(&package, project_dir) {
+        Some(id) => id,
+        None => bail!(
+            "Could not detect a supported framework in {}",
+            path.display()
+        ),
+    };
+    let framework = match registry
+        .list_frameworks()?
+        .into_iter()
+        .find(|f| f.id == framework_id)
+    {
+        Some(f) => f,
+        None => bail!(
+            "Detected framework '{}' is not in the registry",
+            framework_id
+        ),
+    };
+
+    let mut features = Vec::new();
+    for feature in registry.features_for_framework(&framework.id)? {
+        let deps =
+            registry.get_dependencies(Some(&framework.id), std::slice::from_ref(&feature.id))?;
+        let present = !deps.is_empty() && deps.iter().all(|d| declares(&package, &d.package_name));
+        if feature.required || present {
+            features.push(feature.id);
+        }
+    }
+
+    Ok(ProjectSpec {
+        spec_version: CURRENT_SPEC_VERSION,
+        language: framework.language,
+        framework: framework.id,
+        features,
+        hooks: Default::default(),
+        git: None,
+    })
+}
+
+fn detect_framework(package: &Value, project_dir: &Path) -> Option<&'static str> {
+    if declares(package, "react") {
+        if project_dir.join("tsconfig.json").exists() {
+            Some("react-ts")
+        } else {
+            Some("react")
+        }
+    } else {
+        None
+    }
+}
+
+/// Whether `package.json` lists `name` as a dependency of any kind.
+pub fn declares(package: &Value, name: &str) -> bool {
+    ["dependencies", "devDependencies"]
+        .iter()
+        .any(|section| package[section].get(name).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::sqlite::SqliteRegistry;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_detect_from_package_json() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+        let project = dir.path().join("app");
+        std::fs::create_dir(&project).unwrap();
+        std::fs::write(
+            project.join("package.json"),
+            r#"{"dependencies": {"react": "^18.0.0"}, "devDependencies": {"tailwindcss": "^3.4.0"}}"#,
+        )
+        .unwrap();
+
+        let spec = detect_spec(&project, &registry).unwrap();
+        assert_eq!(spec.framework, "react");
+        assert_eq!(spec.language, "javascript");
+        assert_eq!(spec.features, vec!["tailwind"]);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::manifest::LOCK_FILE;
 use crate::plan::*;
 use crate::registry::Registry;
-use crate::spec::ProjectSpec;
-use anyhow::{bail, Result};
+use crate::spec::{GitSpec, ProjectSpec};
+use crate::steps::{self, ContributedStep};
+use anyhow::{bail, Context, Result};
 
 pub struct Engine;
 
@@ -34,7 +36,21 @@ impl Engine {
         // Separate into normal and dev dependencies
         let mut dependencies = Vec::new();
         let mut dev_dependencies = Vec::new();
+        let mut provenance = Vec::new();
         for d in deps {
+            provenance.push(Provenance {
+                kind: if d.is_dev {
+                    ItemKind::DevDependency
+                } else {
+                    ItemKind::Dependency
+                },
+                item: d.package_name.clone(),
+                feature_id: Some(d.feature_id),
+                framework_id: d.framework_id,
+                layer: registry.layer().to_string(),
+                table: "dependencies".to_string(),
+                row_id: Some(d.id),
+            });
             let dep = DependencyInstall {
                 name: d.package_name,
                 version: d.version_constraint,
@@ -48,14 +64,23 @@ impl Engine {
 
         // 4. Get config mutations
         let mutations = registry.get_config_mutations(&spec.framework, &spec.features)?;
-        let file_modifications = mutations
-            .into_iter()
-            .map(|m| FileModification {
+        let mut file_modifications = Vec::new();
+        for m in mutations {
+            provenance.push(Provenance {
+                kind: ItemKind::FileModification,
+                item: m.file_path.clone(),
+                feature_id: Some(m.feature_id),
+                framework_id: Some(m.framework_id),
+                layer: registry.layer().to_string(),
+                table: "config_mutations".to_string(),
+                row_id: Some(m.id),
+            });
+            file_modifications.push(FileModification {
                 path: m.file_path,
                 mutation_type: m.mutation_type,
                 content: m.content,
-            })
-            .collect();
+            });
+        }
 
         // 5. Determine scaffold step (if any)
         let scaffold = if let Some(cmd) = registry.get_scaffold_command(&spec.framework)? {
@@ -65,31 +90,494 @@ impl Engine {
             if parts.is_empty() {
                 None
             } else {
-                Some(ScaffoldStep {
+                provenance.push(Provenance {
+                    kind: ItemKind::Scaffold,
+                    item: cmd.clone(),
+                    feature_id: None,
+                    framework_id: Some(spec.framework.clone()),
+                    layer: registry.layer().to_string(),
+                    table: "frameworks".to_string(),
+                    row_id: None,
+                });
+                Some(CommandStep {
                     command: parts[0].to_string(),
                     args: parts[1..].iter().map(|s| s.to_string()).collect(),
+                    env: Default::default(),
+                    cwd: None,
+                    timeout_secs: None,
+                    creates: Vec::new(),
                 })
             }
         } else {
             None
         };
 
-        // 6. Build the final plan (file_writes and scripts are empty for now)
-        Ok(ExecutionPlan {
+        // 6. Steps the registry contributes at specific phases
+        let mut contributed = Vec::new();
+        for step in registry.get_steps(&spec.framework, &spec.features)? {
+            let phase: Phase =
+                serde_json::from_value(serde_json::Value::String(step.phase.clone()))
+                    .with_context(|| {
+                        format!(
+                            "Registry step '{}' has unknown phase '{}'",
+                            step.name, step.phase
+                        )
+                    })?;
+            let action: StepAction = serde_json::from_str(&step.action)
+                .with_context(|| format!("Registry step '{}' has an invalid action", step.name))?;
+            provenance.push(Provenance {
+                kind: ItemKind::Step,
+                item: step.name.clone(),
+                feature_id: step.feature_id,
+                framework_id: step.framework_id,
+                layer: registry.layer().to_string(),
+                table: "plan_steps".to_string(),
+                row_id: Some(step.id),
+            });
+            contributed.push(ContributedStep {
+                id: step.name,
+                phase,
+                when: None,
+                action,
+            });
+        }
+
+        // 7. Lifecycle hooks, first the registry's, then the spec's own
+        for hook in registry.get_hooks(&spec.framework, &spec.features)? {
+            let point: HookPoint =
+                serde_json::from_value(serde_json::Value::String(hook.hook_point.clone()))
+                    .with_context(|| {
+                        format!(
+                            "Registry hook '{}' has unknown hook point '{}'",
+                            hook.name, hook.hook_point
+                        )
+                    })?;
+            let command: CommandStep = serde_json::from_str(&hook.command)
+                .with_context(|| format!("Registry hook '{}' has an invalid command", hook.name))?;
+            provenance.push(Provenance {
+                kind: ItemKind::Hook,
+                item: hook.name.clone(),
+                feature_id: hook.feature_id,
+                framework_id: hook.framework_id,
+                layer: registry.layer().to_string(),
+                table: "hooks".to_string(),
+                row_id: Some(hook.id),
+            });
+            contributed.push(ContributedStep {
+                id: hook.name,
+                phase: point.phase(),
+                when: None,
+                action: StepAction::RunCommand(command),
+            });
+        }
+        for (point, commands) in &spec.hooks {
+            for (i, command) in commands.iter().enumerate() {
+                let id = format!("{}#{}", point.as_str(), i + 1);
+                provenance.push(Provenance {
+                    kind: ItemKind::Hook,
+                    item: id.clone(),
+                    feature_id: None,
+                    framework_id: None,
+                    layer: "spec".to_string(),
+                    table: "hooks".to_string(),
+                    row_id: None,
+                });
+                contributed.push(ContributedStep {
+                    id,
+                    phase: point.phase(),
+                    when: None,
+                    action: StepAction::RunCommand(command.clone()),
+                });
+            }
+        }
+
+        // 8. Version control, last so the final commit sees every other change
+        if let Some(git) = &spec.git {
+            let patterns = registry.get_gitignore_patterns(&spec.framework, &spec.features)?;
+            for p in &patterns {
+                provenance.push(Provenance {
+                    kind: ItemKind::GitignorePattern,
+                    item: p.pattern.clone(),
+                    feature_id: p.feature_id.clone(),
+                    framework_id: p.framework_id.clone(),
+                    layer: registry.layer().to_string(),
+                    table: "gitignore_patterns".to_string(),
+                    row_id: Some(p.id),
+                });
+            }
+            let patterns = patterns.into_iter().map(|p| p.pattern).collect();
+            contributed.extend(git_steps(spec, git, scaffold.is_some(), patterns));
+        }
+
+        // 9. Build the final plan (file_writes and scripts are empty for now)
+        let mut plan = ExecutionPlan {
+            plan_version: PLAN_VERSION,
+            steps: vec![],
             scaffold,
             dependencies,
             dev_dependencies,
             file_writes: vec![], // could be filled from templates later
             file_modifications,
             scripts: vec![], // could be filled from package.json scripts
+            provenance: Some(provenance),
+            registry_version: Some(registry.version()?),
+            plan_hash: None,
+        };
+        // Steps are derived from the canonical order of the compatibility fields
+        plan.canonicalize();
+        plan.steps = steps::assemble(&plan, contributed);
+        scope_commit(&mut plan);
+        // Hooks come from outside the registry and may collide or point outside the target
+        plan.validate()?;
+        plan.seal()?;
+        Ok(plan)
+    }
+
+    /// Work out what going back from the plan the project was last brought to (`old`,
+    /// normally from its lock) to the smaller `target` spec takes.
+    pub fn resolve_removal(
+        old: &ExecutionPlan,
+        target: &ProjectSpec,
+        registry: &dyn Registry,
+    ) -> Result<Removal> {
+        let new = Self::resolve(target, registry)?;
+
+        let mut contributed: Vec<ContributedStep> = uninstall_step(old, &new).into_iter().collect();
+        contributed.extend(
+            new.steps
+                .iter()
+                .filter(|s| s.phase == Phase::Verify)
+                .map(|s| ContributedStep {
+                    id: s.id.clone(),
+                    phase: s.phase,
+                    when: s.when,
+                    action: s.action.clone(),
+                }),
+        );
+
+        let builtin: Vec<String> = steps::assemble(old, vec![])
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        let irreversible = old
+            .steps
+            .iter()
+            .filter(|s| {
+                !builtin.contains(&s.id) && s.phase > Phase::Scaffold && s.phase != Phase::Verify
+            })
+            .filter(|s| !new.steps.contains(s))
+            .map(|s| s.id.clone())
+            .collect();
+
+        let mut plan = ExecutionPlan {
+            plan_version: PLAN_VERSION,
+            steps: vec![],
+            scaffold: None,
+            dependencies: vec![],
+            dev_dependencies: vec![],
+            file_writes: vec![],
+            file_modifications: vec![],
+            scripts: vec![],
+            provenance: None,
+            registry_version: new.registry_version,
+            plan_hash: None,
+        };
+        plan.steps = steps::assemble(&plan, contributed);
+        plan.seal()?;
+
+        Ok(Removal {
+            plan,
+            modifications: old
+                .file_modifications
+                .iter()
+                .filter(|m| !new.file_modifications.contains(m))
+                .cloned()
+                .collect(),
+            file_writes: old
+                .file_writes
+                .iter()
+                .filter(|w| !new.file_writes.contains(w))
+                .cloned()
+                .collect(),
+            irreversible,
         })
     }
+
+    /// Resolve only what `target` adds on top of `old`, the plan the project was last
+    /// brought to (normally from its lock).
+    ///
+    /// Packages, mutations and registry steps already present in the old plan are left
+    /// out, and nothing at or before the scaffold phase runs again, except creating a
+    /// branch for the commit. Packages `target` no longer needs are uninstalled.
+    /// Verification checks are always kept so the augmented project is checked as a whole.
+    pub fn resolve_delta(
+        old: &ExecutionPlan,
+        target: &ProjectSpec,
+        registry: &dyn Registry,
+    ) -> Result<ExecutionPlan> {
+        let new = Self::resolve(target, registry)?;
+
+        let mut plan = new.clone();
+        plan.scaffold = None;
+        plan.dependencies.retain(|d| !old.dependencies.contains(d));
+        plan.dev_dependencies
+            .retain(|d| !old.dev_dependencies.contains(d));
+        plan.file_writes.retain(|w| !old.file_writes.contains(w));
+        plan.file_modifications
+            .retain(|m| !old.file_modifications.contains(m));
+        plan.scripts.retain(|s| !old.scripts.contains(s));
+        if let (Some(provenance), Some(old_provenance)) = (&mut plan.provenance, &old.provenance) {
+            provenance.retain(|p| !old_provenance.contains(p));
+        }
+
+        // Steps the fixed-phase fields produce are rebuilt from the trimmed fields below
+        let builtin: Vec<String> = steps::assemble(&new, vec![])
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        let uninstall = uninstall_step(old, &new);
+        let contributed = new
+            .steps
+            .into_iter()
+            .filter(|s| {
+                s.id == "git-branch" || (!builtin.contains(&s.id) && s.phase > Phase::Scaffold)
+            })
+            .filter(|s| s.id == "git-branch" || s.phase == Phase::Verify || !old.steps.contains(s))
+            .map(|s| {
+                let mut step = ContributedStep {
+                    id: s.id,
+                    phase: s.phase,
+                    when: s.when,
+                    action: s.action,
+                };
+                if step.id == "git-branch" {
+                    rename_branch(&mut step.action, old, target, new.registry_version);
+                }
+                step
+            });
+        let contributed = uninstall.into_iter().chain(contributed).collect();
+        plan.steps = steps::assemble(&plan, contributed);
+        scope_commit(&mut plan);
+        plan.plan_hash = None;
+        plan.seal()?;
+        Ok(plan)
+    }
+}
+
+/// Uninstalls the packages of `old` that `new` no longer needs, if there are any.
+fn uninstall_step(old: &ExecutionPlan, new: &ExecutionPlan) -> Option<ContributedStep> {
+    let still_needed = |name: &str| {
+        new.dependencies
+            .iter()
+            .chain(&new.dev_dependencies)
+            .any(|d| d.name == name)
+    };
+    let mut packages: Vec<String> = old
+        .dependencies
+        .iter()
+        .chain(&old.dev_dependencies)
+        .filter(|d| !still_needed(&d.name))
+        .map(|d| d.name.clone())
+        .collect();
+    if packages.is_empty() {
+        return None;
+    }
+    packages.sort();
+    packages.dedup();
+    Some(ContributedStep {
+        id: "uninstall".to_string(),
+        phase: Phase::Install,
+        when: None,
+        action: StepAction::RunCommand(CommandStep {
+            command: "npm".to_string(),
+            args: std::iter::once("uninstall".to_string())
+                .chain(packages)
+                .collect(),
+            env: Default::default(),
+            cwd: None,
+            timeout_secs: None,
+            creates: Vec::new(),
+        }),
+    })
+}
+
+/// The branch a project is set up on when the spec does not name one.
+const DEFAULT_BRANCH: &str = "orchestrator/setup";
+
+/// Name the branch a delta plan is committed on after what it changes, since the one
+/// the project was set up on usually exists already.
+fn rename_branch(
+    action: &mut StepAction,
+    old: &ExecutionPlan,
+    target: &ProjectSpec,
+    version: Option<u32>,
+) {
+    let StepAction::RunCommand(command) = action else {
+        return;
+    };
+    let Some(branch) = command.args.last_mut() else {
+        return;
+    };
+    let added: Vec<&str> = match &old.provenance {
+        Some(provenance) => target
+            .features
+            .iter()
+            .filter(|f| !provenance.iter().any(|p| p.feature_id.as_ref() == Some(f)))
+            .map(String::as_str)
+            .collect(),
+        None => Vec::new(),
+    };
+    let change = match (added.is_empty(), version) {
+        (false, _) => format!("add-{}", added.join("-")),
+        (true, Some(version)) => format!("upgrade-{}", version),
+        (true, None) => "upgrade".to_string(),
+    };
+    *branch = match branch.as_str() {
+        DEFAULT_BRANCH => format!("orchestrator/{}", change),
+        custom => format!("{}-{}", custom, change),
+    };
+}
+
+/// Narrow the final commit of a plan that does not scaffold to the files its steps
+/// touch or its commands say they create, and the lock, so changes the user has staged or left lying
+/// around stay out of it.
+fn scope_commit(plan: &mut ExecutionPlan) {
+    if plan.scaffold.is_some() {
+        return;
+    }
+    let mut paths: Vec<String> = plan
+        .steps
+        .iter()
+        .filter(|s| s.phase != Phase::Verify)
+        .flat_map(|s| match &s.action {
+            StepAction::RunCommand(command) => command.creates.clone(),
+            action => steps::touched_path(action)
+                .map(str::to_string)
+                .into_iter()
+                .collect(),
+        })
+        .collect();
+    let npm = plan.steps.iter().any(|s| match &s.action {
+        StepAction::InstallPackages { .. } => true,
+        StepAction::RunCommand(command) => command.command == "npm",
+        _ => false,
+    });
+    if npm {
+        paths.extend(["package.json", "package-lock.json"].map(String::from));
+    }
+    // Written just before the commit, so it records the state being committed
+    paths.push(LOCK_FILE.to_string());
+    paths.sort();
+    paths.dedup();
+    for step in &mut plan.steps {
+        if let StepAction::GitCommit {
+            paths: committed, ..
+        } = &mut step.action
+        {
+            if step.phase == Phase::PostApply {
+                *committed = paths.clone();
+            }
+        }
+    }
+}
+
+/// What taking features out of a project involves.
+pub struct Removal {
+    /// Uninstalls packages no other feature needs, then runs the remaining checks.
+    pub plan: ExecutionPlan,
+    /// Mutations the removed features had applied.
+    pub modifications: Vec<FileModification>,
+    /// Files the removed features had written.
+    pub file_writes: Vec<FileWrite>,
+    /// Registry steps whose effects cannot be undone automatically.
+    pub irreversible: Vec<String>,
+}
+
+/// Steps that put the project under version control.
+///
+/// In a new repository everything lands in one initial commit. Inside an existing one,
+/// a branch is created first and the scaffold output is committed separately from the
+/// orchestrator's own changes.
+fn git_steps(
+    spec: &ProjectSpec,
+    git: &GitSpec,
+    scaffolded: bool,
+    patterns: Vec<String>,
+) -> Vec<ContributedStep> {
+    let mut steps = Vec::new();
+    let branch = git
+        .branch
+        .clone()
+        .unwrap_or_else(|| DEFAULT_BRANCH.to_string());
+    steps.push(ContributedStep {
+        id: "git-branch".to_string(),
+        phase: Phase::PreScaffold,
+        when: Some(RepoCondition::ExistingRepo),
+        action: StepAction::RunCommand(CommandStep {
+            command: "git".to_string(),
+            args: vec!["checkout".to_string(), "-b".to_string(), branch],
+            env: Default::default(),
+            cwd: None,
+            timeout_secs: None,
+            creates: Vec::new(),
+        }),
+    });
+    if git.init {
+        steps.push(ContributedStep {
+            id: "git-init".to_string(),
+            phase: Phase::Scaffold,
+            when: Some(RepoCondition::NewRepo),
+            action: StepAction::GitInit {
+                initial_branch: git.initial_branch.clone(),
+            },
+        });
+    }
+    if scaffolded {
+        steps.push(ContributedStep {
+            id: "git-commit-scaffold".to_string(),
+            phase: Phase::Scaffold,
+            when: Some(RepoCondition::ExistingRepo),
+            action: StepAction::GitCommit {
+                message: format!("Scaffold {} project", spec.framework),
+                author: git.author.clone(),
+                paths: vec![".".to_string()],
+            },
+        });
+    }
+    if !patterns.is_empty() {
+        steps.push(ContributedStep {
+            id: "gitignore".to_string(),
+            phase: Phase::Files,
+            when: None,
+            action: StepAction::GitIgnore { patterns },
+        });
+    }
+    steps.push(ContributedStep {
+        id: "git-commit".to_string(),
+        phase: Phase::PostApply,
+        // Without init there is nothing to commit into unless a repository already exists
+        when: if git.init {
+            None
+        } else {
+            Some(RepoCondition::ExistingRepo)
+        },
+        action: StepAction::GitCommit {
+            message: git
+                .commit_message
+                .clone()
+                .unwrap_or_else(|| format!("Set up {} project", spec.framework)),
+            author: git.author.clone(),
+            // Narrowed to what the plan touches once its steps are known
+            paths: vec![".".to_string()],
+        },
+    });
+    steps
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::registry::sqlite::SqliteRegistry;
+    use crate::registry::sqlite::{migrations, SqliteRegistry};
     use tempfile::tempdir;
 
     #[test]
@@ -103,14 +591,20 @@ mod tests {
             language: "javascript".to_string(),
             framework: "react".to_string(),
             features: vec!["typescript".to_string()],
+            hooks: Default::default(),
+            git: None,
         };
 
         let plan = Engine::resolve(&spec, &registry).unwrap();
         assert!(plan.scaffold.is_some());
         assert_eq!(plan.dependencies.len(), 0); // react and react-dom are in scaffold? Not in our data; we only have feature deps.
-        // In our seed, typescript is dev dep only.
-        assert_eq!(plan.dev_dependencies.len(), 1);
+        assert_eq!(plan.dev_dependencies.len(), 1); // In our seed, typescript is dev dep only.
         assert_eq!(plan.dev_dependencies[0].name, "typescript");
+        assert_eq!(plan.registry_version, Some(migrations().len() as u32));
+        let ids: Vec<&str> = plan.steps.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["scaffold", "install-dev", "typecheck", "build"]);
+        assert_eq!(plan.steps[2].phase, Phase::Verify);
+        plan.verify_hash().unwrap();
     }
 
     #[test]
@@ -124,9 +618,290 @@ mod tests {
             language: "python".to_string(),
             framework: "react".to_string(),
             features: vec![],
+            hooks: Default::default(),
+            git: None,
         };
 
         let result = Engine::resolve(&spec, &registry);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_registry_contributes_post_install_step() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let spec = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec!["tailwind".to_string()],
+            hooks: Default::default(),
+            git: None,
+        };
+
+        let plan = Engine::resolve(&spec, &registry).unwrap();
+        let step = plan.steps.iter().find(|s| s.id == "tailwind-init").unwrap();
+        assert_eq!(step.phase, Phase::PostInstall);
+        assert_eq!(step.depends_on, vec!["install-dev"]);
+        match &step.action {
+            StepAction::RunCommand(cmd) => {
+                assert_eq!(cmd.argv(), vec!["npx", "tailwindcss", "init", "-p"])
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_spec_hooks_become_steps() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let mut spec = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec![],
+            hooks: Default::default(),
+            git: None,
+        };
+        spec.hooks.insert(
+            HookPoint::AfterInstall,
+            vec![CommandStep {
+                command: "npx".to_string(),
+                args: vec!["husky".to_string(), "install".to_string()],
+                env: Default::default(),
+                cwd: None,
+                timeout_secs: Some(60),
+                creates: Vec::new(),
+            }],
+        );
+
+        let plan = Engine::resolve(&spec, &registry).unwrap();
+        let ids: Vec<&str> = plan.steps.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["scaffold", "after_install#1", "build"]);
+        assert_eq!(plan.steps[1].phase, Phase::PostInstall);
+        assert_eq!(plan.steps[1].depends_on, vec!["scaffold"]);
+        let provenance = plan.provenance.unwrap();
+        assert!(provenance
+            .iter()
+            .any(|p| p.item == "after_install#1" && p.layer == "spec"));
+    }
+
+    #[test]
+    fn test_git_block_adds_commit_steps() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let spec = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec!["eslint".to_string()],
+            hooks: Default::default(),
+            git: Some(GitSpec {
+                init: true,
+                initial_branch: Some("main".to_string()),
+                branch: None,
+                commit_message: None,
+                author: None,
+            }),
+        };
+
+        let plan = Engine::resolve(&spec, &registry).unwrap();
+        let ids: Vec<&str> = plan.steps.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "git-branch",
+                "scaffold",
+                "git-init",
+                "git-commit-scaffold",
+                "install-dev",
+                "gitignore",
+                "git-commit",
+                "build"
+            ]
+        );
+        let gitignore = plan.steps.iter().find(|s| s.id == "gitignore").unwrap();
+        match &gitignore.action {
+            StepAction::GitIgnore { patterns } => {
+                assert!(patterns.contains(&".eslintcache".to_string()))
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+        let commit = plan.steps.iter().find(|s| s.id == "git-commit").unwrap();
+        assert_eq!(commit.when, None);
+        assert_eq!(commit.depends_on, vec!["gitignore"]);
+    }
+
+    #[test]
+    fn test_delta_skips_scaffold_and_existing_items() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let base = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec!["eslint".to_string()],
+            hooks: Default::default(),
+            git: None,
+        };
+        let mut target = base.clone();
+        target.features.push("tailwind".to_string());
+
+        let plan = Engine::resolve_delta(
+            &Engine::resolve(&base, &registry).unwrap(),
+            &target,
+            &registry,
+        )
+        .unwrap();
+        assert!(plan.scaffold.is_none());
+        let names: Vec<&str> = plan
+            .dev_dependencies
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["tailwindcss"]);
+        let ids: Vec<&str> = plan.steps.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "install-dev",
+                "tailwind-init",
+                "modify:vite.config.js#1",
+                "build"
+            ]
+        );
+        assert_eq!(plan.steps[0].depends_on, Vec::<String>::new());
+        plan.verify_hash().unwrap();
+    }
+
+    #[test]
+    fn test_delta_commits_its_own_paths_on_a_new_branch() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let base = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec!["eslint".to_string()],
+            hooks: Default::default(),
+            git: Some(GitSpec {
+                init: false,
+                initial_branch: None,
+                branch: None,
+                commit_message: None,
+                author: None,
+            }),
+        };
+        let mut target = base.clone();
+        target.features.push("tailwind".to_string());
+
+        let plan = Engine::resolve_delta(
+            &Engine::resolve(&base, &registry).unwrap(),
+            &target,
+            &registry,
+        )
+        .unwrap();
+        let branch = plan.steps.iter().find(|s| s.id == "git-branch").unwrap();
+        match &branch.action {
+            StepAction::RunCommand(command) => {
+                assert_eq!(
+                    command.argv(),
+                    vec!["git", "checkout", "-b", "orchestrator/add-tailwind"]
+                )
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+        let commit = plan.steps.iter().find(|s| s.id == "git-commit").unwrap();
+        match &commit.action {
+            StepAction::GitCommit { paths, .. } => {
+                assert_eq!(
+                    paths,
+                    &vec![
+                        ".gitignore",
+                        "orchestrator.lock",
+                        "package-lock.json",
+                        "package.json",
+                        "postcss.config.js",
+                        "tailwind.config.js",
+                        "vite.config.js"
+                    ]
+                )
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_delta_against_older_plan_upgrades_packages() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+        let spec = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec!["tailwind".to_string()],
+            hooks: Default::default(),
+            git: None,
+        };
+        // A plan locked against an older registry
+        let mut old = Engine::resolve(&spec, &registry).unwrap();
+        old.dev_dependencies[0].version = "^2.0".to_string();
+        old.dev_dependencies.push(DependencyInstall {
+            name: "left-pad".to_string(),
+            version: "^1.0".to_string(),
+        });
+
+        let plan = Engine::resolve_delta(&old, &spec, &registry).unwrap();
+        assert_eq!(plan.dev_dependencies[0].version, "^3.0");
+        assert!(plan.file_modifications.is_empty());
+        let uninstall = plan.steps.iter().find(|s| s.id == "uninstall").unwrap();
+        match &uninstall.action {
+            StepAction::RunCommand(command) => {
+                assert_eq!(command.argv(), vec!["npm", "uninstall", "left-pad"])
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+        assert!(plan.steps.iter().any(|s| s.id == "install-dev"));
+        assert!(!plan.steps.iter().any(|s| s.id == "tailwind-init"));
+    }
+
+    #[test]
+    fn test_removal_uninstalls_and_reverses() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let base = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec!["tailwind".to_string(), "eslint".to_string()],
+            hooks: Default::default(),
+            git: None,
+        };
+        let mut target = base.clone();
+        target.features.retain(|f| f != "tailwind");
+
+        let removal = Engine::resolve_removal(
+            &Engine::resolve(&base, &registry).unwrap(),
+            &target,
+            &registry,
+        )
+        .unwrap();
+        let ids: Vec<&str> = removal.plan.steps.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["uninstall", "build"]);
+        match &removal.plan.steps[0].action {
+            StepAction::RunCommand(cmd) => {
+                assert_eq!(cmd.argv(), vec!["npm", "uninstall", "tailwindcss"])
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+        assert_eq!(removal.modifications.len(), 1);
+        assert_eq!(removal.modifications[0].path, "vite.config.js");
+        assert_eq!(removal.irreversible, vec!["tailwind-init"]);
+    }
 }
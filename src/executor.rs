@@ -1,89 +1,1208 @@
-use crate::plan::ExecutionPlan;
-use anyhow::{Context, Result};
+use crate::keyvalue::{self, KEY_VALUE_MUTATIONS};
+use crate::logging::{Event, Reporter, LOG_DIR};
+use crate::manifest::{sha256_hex, AppliedChange};
+use crate::merge::{base_path, merge3};
+use crate::mutate::{apply_text, json_patch_present, TEXT_MUTATIONS};
+use crate::plan::{
+    parse_mode, CommandStep, DependencyInstall, ExecutionPlan, FileModification, Phase,
+    RepoCondition, Step, StepAction,
+};
+use crate::policy::ExecutionPolicy;
+use crate::process::{self, ProcessSpec};
+use crate::sandbox::{self, Sandbox};
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::Path;
-use std::process::Command;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Upper bound for a dependency install step.
+const INSTALL_TIMEOUT_SECS: u64 = 30 * 60;
+
+/// See [`ExecuteOptions::before_commit`].
+pub type BeforeCommit<'a> = dyn Fn(&Applied) -> Result<()> + 'a;
+
+/// Knobs for a single `execute` call.
+#[derive(Clone)]
+pub struct ExecuteOptions<'a> {
+    /// Maximum number of commands running at the same time.
+    pub jobs: usize,
+    /// Leave out the `verify` phase.
+    pub skip_verify: bool,
+    /// Generated content existing files were last written from, by path, for files with
+    /// no copy under `.orchestrator/base/` yet. A `write_file` onto a file with a base is
+    /// three-way merged with it instead of overwriting user edits.
+    pub merge_bases: BTreeMap<String, String>,
+    /// Called with the changes made once every step but the final commit has run, so
+    /// what it writes (the project's lock) is committed along with them.
+    pub before_commit: Option<&'a BeforeCommit<'a>>,
+}
+
+/// What an `execute` call changed.
+#[derive(Debug, Default)]
+pub struct Applied {
+    /// File changes in plan order.
+    pub changes: Vec<AppliedChange>,
+    /// Files left with conflict markers by a merge.
+    pub conflicts: Vec<String>,
+}
+
+impl Default for ExecuteOptions<'_> {
+    fn default() -> Self {
+        ExecuteOptions {
+            jobs: 1,
+            skip_verify: false,
+            merge_bases: BTreeMap::new(),
+            before_commit: None,
+        }
+    }
+}
+
+/// Opens the apply log once the scaffolder is done with the target directory.
+struct LogGate<'a> {
+    sandbox: &'a Sandbox,
+    reporter: &'a Reporter,
+    opened: Mutex<bool>,
+}
+
+impl<'a> LogGate<'a> {
+    fn new(sandbox: &'a Sandbox, reporter: &'a Reporter) -> Self {
+        LogGate {
+            sandbox,
+            reporter,
+            opened: Mutex::new(false),
+        }
+    }
+
+    /// Call before starting `step`; most scaffolders refuse a non-empty directory.
+    fn before(&self, step: &Step) -> Result<()> {
+        if step.phase > Phase::Scaffold {
+            self.open()?;
+        }
+        Ok(())
+    }
+
+    fn open(&self) -> Result<()> {
+        let mut opened = self.opened.lock().unwrap();
+        if !*opened {
+            self.reporter.open_in(&self.sandbox.resolve(LOG_DIR)?)?;
+            *opened = true;
+        }
+        Ok(())
+    }
+}
+
+/// The target directory and what it looked like before apply started.
+struct Workspace {
+    sandbox: Sandbox,
+    repo: RepoCondition,
+    /// File changes made so far, by step id.
+    changes: Mutex<Vec<(String, AppliedChange)>>,
+    merge_bases: BTreeMap<String, String>,
+    conflicts: Mutex<Vec<String>>,
+}
 
 pub struct Executor;
 
 impl Executor {
+    /// Every command the plan would spawn, as exact argv.
+    pub fn commands(plan: &ExecutionPlan) -> Vec<Vec<String>> {
+        plan.effective_steps()
+            .iter()
+            .flat_map(|step| Self::commands_for(&step.action))
+            .map(|command| command.argv())
+            .collect()
+    }
+
+    /// The external commands behind a step, in order; empty for file steps.
+    fn commands_for(action: &StepAction) -> Vec<CommandStep> {
+        match action {
+            StepAction::RunCommand(command) => vec![command.clone()],
+            StepAction::InstallPackages { packages, dev } => {
+                vec![Self::install_command(packages, *dev)]
+            }
+            StepAction::GitInit { initial_branch } => {
+                let mut args = vec!["init".to_string()];
+                if let Some(branch) = initial_branch {
+                    args.push("-b".to_string());
+                    args.push(branch.clone());
+                }
+                vec![git(args)]
+            }
+            StepAction::GitCommit {
+                message,
+                author,
+                paths,
+            } => {
+                if paths.is_empty() {
+                    return Vec::new();
+                }
+                let pathspec = || std::iter::once("--".to_string()).chain(paths.iter().cloned());
+                // Without an author the user's own git identity is used. It is passed as
+                // arguments rather than environment so the policy can trust the command.
+                let mut commit = Vec::new();
+                if let Some(author) = author {
+                    commit.extend([
+                        "-c".to_string(),
+                        format!("user.name={}", author.name),
+                        "-c".to_string(),
+                        format!("user.email={}", author.email),
+                    ]);
+                }
+                commit.extend([
+                    "commit".to_string(),
+                    "-q".to_string(),
+                    "-m".to_string(),
+                    message.clone(),
+                ]);
+                commit.extend(pathspec());
+                vec![
+                    git(["add", "-A"]
+                        .map(String::from)
+                        .into_iter()
+                        .chain(pathspec())
+                        .collect()),
+                    git(["diff", "--cached", "--quiet"]
+                        .map(String::from)
+                        .into_iter()
+                        .chain(pathspec())
+                        .collect()),
+                    git(commit),
+                ]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Build the `npm install` command for one group of packages.
+    fn install_command(packages: &[DependencyInstall], dev: bool) -> CommandStep {
+        let mut args = vec!["install".to_string()];
+        if dev {
+            args.push("--save-dev".to_string());
+        }
+        for dep in packages {
+            args.push(format!("{}@{}", dep.name, dep.version));
+        }
+        CommandStep {
+            command: "npm".to_string(),
+            args,
+            env: Default::default(),
+            cwd: None,
+            timeout_secs: Some(INSTALL_TIMEOUT_SECS),
+            creates: Vec::new(),
+        }
+    }
+
+    fn run_command(
+        id: &str,
+        command: &CommandStep,
+        sandbox: &Sandbox,
+        reporter: &Reporter,
+    ) -> Result<()> {
+        Self::spawn(id, command, sandbox, reporter, process::run)
+    }
+
+    /// Run `command` in the sandbox as part of step `id`, leaving its exit status to `run`.
+    fn spawn<T>(
+        id: &str,
+        command: &CommandStep,
+        sandbox: &Sandbox,
+        reporter: &Reporter,
+        run: impl FnOnce(&ProcessSpec) -> Result<T>,
+    ) -> Result<T> {
+        let argv = command.argv();
+        let cwd = match &command.cwd {
+            Some(cwd) => {
+                let dir = sandbox
+                    .resolve_dir(cwd)
+                    .with_context(|| format!("Refusing plan entry steps[{}].cwd", id))?;
+                fs::create_dir_all(&dir)?;
+                dir
+            }
+            None => sandbox.root().to_path_buf(),
+        };
+        reporter.step(id, Some(&argv), || {
+            run(&ProcessSpec {
+                label: id,
+                argv: &argv,
+                cwd: &cwd,
+                env: &command.env,
+                timeout: command.timeout_secs.map(Duration::from_secs),
+                reporter,
+            })
+        })
+    }
+
     /// Execute the plan in the given target directory.
-    pub fn execute(plan: &ExecutionPlan, target_dir: &Path) -> Result<()> {
+    ///
+    /// Every command is checked against `policy` before the first step starts. Steps run
+    /// once their `depends_on` edges are satisfied, up to `options.jobs` commands at a time.
+    /// The final commit runs last, after `options.before_commit`, followed by the `verify`
+    /// phase unless it is skipped. All output is reported through `reporter`, whose log
+    /// ends up under `.orchestrator/logs` in the target.
+    ///
+    /// Returns the file changes made, for the project's lock manifest.
+    pub fn execute(
+        plan: &ExecutionPlan,
+        target_dir: &Path,
+        policy: &ExecutionPolicy,
+        reporter: &Reporter,
+        options: &ExecuteOptions,
+    ) -> Result<Applied> {
+        // Refuse unsafe or incompatible plans before touching the filesystem
+        plan.validate()?;
+        let (checks, steps): (Vec<Step>, Vec<Step>) = plan
+            .effective_steps()
+            .into_iter()
+            .partition(|step| step.phase == Phase::Verify);
+        let checks = if options.skip_verify {
+            Vec::new()
+        } else {
+            checks
+        };
+        let (commits, mut steps): (Vec<Step>, Vec<Step>) = steps.into_iter().partition(|step| {
+            step.phase == Phase::PostApply && matches!(step.action, StepAction::GitCommit { .. })
+        });
+        for step in &mut steps {
+            step.depends_on
+                .retain(|id| !commits.iter().any(|commit| &commit.id == id));
+        }
+        // Prompts cannot be answered sensibly once steps run concurrently
+        for step in steps.iter().chain(&commits).chain(&checks) {
+            for command in Self::commands_for(&step.action) {
+                policy.authorize(&command)?;
+            }
+        }
+
         // Create target directory if it doesn't exist
         if !target_dir.exists() {
             fs::create_dir_all(target_dir)
                 .with_context(|| format!("Failed to create directory: {}", target_dir.display()))?;
         }
 
-        // 1. Run scaffold command (if any)
-        if let Some(scaffold) = &plan.scaffold {
-            println!("Running scaffold: {} {:?}", scaffold.command, scaffold.args);
-            let status = Command::new(&scaffold.command)
-                .args(&scaffold.args)
-                .current_dir(target_dir)
-                .status()
-                .with_context(|| format!("Failed to execute scaffold command: {}", scaffold.command))?;
-            if !status.success() {
-                anyhow::bail!("Scaffold command exited with non-zero status");
-            }
-        }
-
-        // 2. Write package.json or install dependencies (simplified)
-        // For a real tool, you'd read existing package.json and merge dependencies.
-        // Here we'll just run npm install with the dependencies.
-        if !plan.dependencies.is_empty() || !plan.dev_dependencies.is_empty() {
-            // Build npm install command
-            let mut cmd = Command::new("npm");
-            cmd.arg("install");
-            for dep in &plan.dependencies {
-                cmd.arg(format!("{}@{}", dep.name, dep.version));
-            }
-            if !plan.dev_dependencies.is_empty() {
-                cmd.arg("--save-dev");
-                for dep in &plan.dev_dependencies {
-                    cmd.arg(format!("{}@{}", dep.name, dep.version));
-                }
-            }
-            println!("Running: {:?}", cmd);
-            let status = cmd
-                .current_dir(target_dir)
-                .status()
-                .context("Failed to run npm install")?;
-            if !status.success() {
-                anyhow::bail!("npm install failed");
-            }
-        }
-
-        // 3. Write files
-        for file_write in &plan.file_writes {
-            let path = target_dir.join(&file_write.path);
-            if let Some(parent) = path.parent() {
-                fs::create_dir_all(parent)?;
-            }
-            fs::write(&path, &file_write.content)
-                .with_context(|| format!("Failed to write file: {}", path.display()))?;
-        }
-
-        // 4. Apply file modifications (very basic)
-        for modification in &plan.file_modifications {
-            let path = target_dir.join(&modification.path);
-            if path.exists() {
-                let mut content = fs::read_to_string(&path)?;
-                // For demo, just append content (only handles text_append)
-                if modification.mutation_type == "text_append" {
-                    content.push_str(&modification.content);
-                    fs::write(&path, content)?;
+        let sandbox = Sandbox::new(target_dir)?;
+        let workspace = Workspace {
+            repo: repo_state(sandbox.root()),
+            sandbox,
+            changes: Mutex::new(Vec::new()),
+            merge_bases: options.merge_bases.clone(),
+            conflicts: Mutex::new(Vec::new()),
+        };
+        let log = LogGate::new(&workspace.sandbox, reporter);
+        let result = if options.jobs <= 1 {
+            // Plan order always satisfies the dependency edges
+            steps.iter().try_for_each(|step| {
+                log.before(step)?;
+                Self::run_step(step, &workspace, reporter)
+            })
+        } else {
+            Self::run_parallel(&steps, &workspace, reporter, &log, options.jobs)
+        };
+        log.open()?;
+        result?;
+        let applied = workspace.applied(&steps);
+        if let Some(before_commit) = options.before_commit {
+            before_commit(&applied)?;
+        }
+        for commit in &commits {
+            Self::run_step(commit, &workspace, reporter)?;
+        }
+        Self::verify(&checks, &workspace, reporter)?;
+        Ok(applied)
+    }
+
+    /// Run every check in order, even after one fails, and report a pass/fail summary.
+    fn verify(checks: &[Step], workspace: &Workspace, reporter: &Reporter) -> Result<()> {
+        if checks.is_empty() {
+            return Ok(());
+        }
+        let mut failed = Vec::new();
+        for check in checks {
+            let passed = Self::run_step(check, workspace, reporter).is_ok();
+            reporter.emit(&Event::VerifyResult {
+                step: &check.id,
+                passed,
+            });
+            if !passed {
+                failed.push(check.id.as_str());
+            }
+        }
+        reporter.emit(&Event::VerifySummary {
+            passed: checks.len() - failed.len(),
+            failed: failed.len(),
+        });
+        if !failed.is_empty() {
+            bail!(
+                "Verification failed: {} (see the log for details, or rerun with --skip-verify)",
+                failed.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Run steps as their dependencies finish, with at most `jobs` commands at once.
+    ///
+    /// Each step reports to the terminal as it runs, while its log lines are replayed in
+    /// plan order, so the log reads the same however the steps interleaved. After the first failure no new
+    /// steps start; those already running are waited for and all errors are reported.
+    fn run_parallel(
+        steps: &[Step],
+        workspace: &Workspace,
+        reporter: &Reporter,
+        log: &LogGate,
+        jobs: usize,
+    ) -> Result<()> {
+        let index: HashMap<&str, usize> = steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| (step.id.as_str(), i))
+            .collect();
+        let mut waiting_on: Vec<usize> = steps.iter().map(|step| step.depends_on.len()).collect();
+        let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); steps.len()];
+        for (i, step) in steps.iter().enumerate() {
+            for dependency in &step.depends_on {
+                dependents[index[dependency.as_str()]].push(i);
+            }
+        }
+
+        let mut ready: BTreeSet<usize> = (0..steps.len()).filter(|&i| waiting_on[i] == 0).collect();
+        let mut finished: Vec<Option<Reporter>> = steps.iter().map(|_| None).collect();
+        let mut started = vec![false; steps.len()];
+        let mut next_replay = 0;
+        let mut running = 0;
+        let mut running_commands = 0;
+        let mut errors: Vec<(usize, anyhow::Error)> = Vec::new();
+        let (done, results) = mpsc::channel::<(usize, Reporter, Result<()>)>();
+
+        thread::scope(|scope| -> Result<()> {
+            loop {
+                if errors.is_empty() {
+                    // Lowest plan index first, so scheduling is deterministic too
+                    let mut startable = Vec::new();
+                    for &i in &ready {
+                        if !Self::commands_for(&steps[i].action).is_empty() {
+                            if running_commands == jobs {
+                                continue;
+                            }
+                            running_commands += 1;
+                        }
+                        startable.push(i);
+                    }
+                    for i in startable {
+                        ready.remove(&i);
+                        log.before(&steps[i])?;
+                        started[i] = true;
+                        running += 1;
+                        let done = done.clone();
+                        let step = &steps[i];
+                        scope.spawn(move || {
+                            let step_reporter = reporter.for_step(&step.id);
+                            let result = Self::run_step(step, workspace, &step_reporter);
+                            let _ = done.send((i, step_reporter, result));
+                        });
+                    }
+                }
+                if running == 0 {
+                    break;
+                }
+
+                let (i, step_log, result) = results.recv().expect("a step is still running");
+                running -= 1;
+                if !Self::commands_for(&steps[i].action).is_empty() {
+                    running_commands -= 1;
+                }
+                finished[i] = Some(step_log);
+                while let Some(step_log) = finished.get_mut(next_replay).and_then(Option::take) {
+                    reporter.replay(step_log);
+                    next_replay += 1;
+                }
+                match result {
+                    Ok(()) => {
+                        for &dependent in &dependents[i] {
+                            waiting_on[dependent] -= 1;
+                            if waiting_on[dependent] == 0 {
+                                ready.insert(dependent);
+                            }
+                        }
+                    }
+                    Err(e) => errors.push((i, e)),
+                }
+            }
+            Ok(())
+        })?;
+
+        // Anything held back behind a step that never ran
+        for step_log in finished.into_iter().flatten() {
+            reporter.replay(step_log);
+        }
+
+        if errors.len() <= 1 {
+            return match errors.pop() {
+                Some((_, e)) => Err(e),
+                None => Ok(()),
+            };
+        }
+        errors.sort_by_key(|(i, _)| *i);
+        let mut message = format!("{} steps failed:", errors.len());
+        for (i, e) in &errors {
+            message.push_str(&format!("\n  - {}: {:#}", steps[*i].id, e));
+        }
+        let skipped: Vec<&str> = steps
+            .iter()
+            .zip(&started)
+            .filter(|(_, started)| !**started)
+            .map(|(step, _)| step.id.as_str())
+            .collect();
+        if !skipped.is_empty() {
+            message.push_str(&format!("\nNot started: {}", skipped.join(", ")));
+        }
+        bail!(message)
+    }
+
+    fn run_step(step: &Step, workspace: &Workspace, reporter: &Reporter) -> Result<()> {
+        if step.when.is_some_and(|when| when != workspace.repo) {
+            reporter.emit(&Event::StepSkipped { step: &step.id });
+            return Ok(());
+        }
+        let sandbox = &workspace.sandbox;
+        if let StepAction::GitCommit {
+            message,
+            author,
+            paths,
+        } = &step.action
+        {
+            // Git refuses a pathspec naming a file that is neither on disk nor tracked, so
+            // paths the plan never got to write are left out. Deleted files are still
+            // tracked and stay in, so their deletion is committed.
+            let mut kept = Vec::new();
+            for path in paths {
+                let tracked = || {
+                    let ls = git(["ls-files", "--error-unmatch", "--", path.as_str()]
+                        .map(String::from)
+                        .to_vec());
+                    Self::spawn(&step.id, &ls, sandbox, reporter, process::succeeds)
+                };
+                if sandbox.root().join(path).exists() || tracked()? {
+                    kept.push(path.clone());
+                }
+            }
+            let commit = StepAction::GitCommit {
+                message: message.clone(),
+                author: author.clone(),
+                paths: kept,
+            };
+            let [add, unchanged, commit] = &Self::commands_for(&commit)[..] else {
+                reporter.emit(&Event::NothingToCommit { step: &step.id });
+                return Ok(());
+            };
+            Self::run_command(&step.id, add, sandbox, reporter)?;
+            if Self::spawn(&step.id, unchanged, sandbox, reporter, process::succeeds)? {
+                reporter.emit(&Event::NothingToCommit { step: &step.id });
+                return Ok(());
+            }
+            return Self::run_command(&step.id, commit, sandbox, reporter);
+        }
+        let commands = Self::commands_for(&step.action);
+        if !commands.is_empty() {
+            return commands
+                .iter()
+                .try_for_each(|command| Self::run_command(&step.id, command, sandbox, reporter));
+        }
+        let refusing = || format!("Refusing plan entry steps[{}]", step.id);
+        reporter.step(&step.id, None, || match &step.action {
+            StepAction::WriteFile { path, content } => {
+                let resolved = sandbox.resolve(path).with_context(refusing)?;
+                let created = !resolved.exists();
+                let base_path = sandbox.resolve(&base_path(path)).with_context(refusing)?;
+                let base = fs::read_to_string(&base_path)
+                    .ok()
+                    .or_else(|| workspace.merge_bases.get(path).cloned());
+                match base.filter(|_| !created) {
+                    Some(base) => {
+                        let current = fs::read_to_string(&resolved)
+                            .with_context(|| format!("Failed to read {}", resolved.display()))?;
+                        let merged = merge3(&base, &current, content);
+                        Self::write_file(&resolved, &merged.text)?;
+                        if merged.conflicts > 0 {
+                            reporter.emit(&Event::MergeConflict {
+                                path,
+                                conflicts: merged.conflicts,
+                            });
+                            workspace.conflicts.lock().unwrap().push(path.clone());
+                        }
+                    }
+                    None => {
+                        Self::write_file(&resolved, content)?;
+                        if created {
+                            workspace.record(
+                                step,
+                                AppliedChange::FileCreated {
+                                    path: path.clone(),
+                                    sha256: sha256_hex(content.as_bytes()),
+                                },
+                            );
+                        }
+                    }
+                }
+                // The next merge starts from what was generated, not from what was merged
+                Self::write_file(&base_path, content)?;
+                reporter.emit(&Event::FileWritten { path });
+                Ok(())
+            }
+            StepAction::ModifyFile(modification) => {
+                let resolved = sandbox.resolve(&modification.path).with_context(refusing)?;
+                if let Some(change) = Self::apply_modification(&resolved, modification)? {
+                    workspace.record(step, change);
+                }
+                reporter.emit(&Event::MutationApplied {
+                    path: &modification.path,
+                    mutation_type: &modification.mutation_type,
+                });
+                Ok(())
+            }
+            StepAction::CreateDir { path } => {
+                let resolved = sandbox.resolve_dir(path).with_context(refusing)?;
+                fs::create_dir_all(&resolved)
+                    .with_context(|| format!("Failed to create directory: {}", resolved.display()))
+            }
+            StepAction::Delete { path } => {
+                // Resolve the parent only, so a symlink is removed rather than followed
+                let relative = sandbox::normalize(path).with_context(refusing)?;
+                let parent = relative.parent().unwrap_or(Path::new("")).to_string_lossy();
+                let target = sandbox
+                    .resolve_dir(&parent)
+                    .with_context(refusing)?
+                    .join(relative.file_name().unwrap_or_default());
+                match fs::symlink_metadata(&target) {
+                    Ok(meta) if meta.is_dir() => fs::remove_dir_all(&target),
+                    Ok(_) => fs::remove_file(&target),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                    Err(e) => Err(e),
+                }
+                .with_context(|| format!("Failed to delete: {}", target.display()))
+            }
+            StepAction::Chmod { path, mode } => {
+                let resolved = sandbox.resolve(path).with_context(refusing)?;
+                let mode = match parse_mode(mode) {
+                    Some(mode) => mode,
+                    None => bail!("Invalid mode '{}' in steps[{}]", mode, step.id),
+                };
+                Self::set_mode(&resolved, mode)
+            }
+            StepAction::GitIgnore { patterns } => {
+                let resolved = sandbox.resolve(".gitignore")?;
+                let mut content = if resolved.exists() {
+                    fs::read_to_string(&resolved)?
                 } else {
-                    println!("Skipping unknown mutation type: {}", modification.mutation_type);
+                    String::new()
+                };
+                let existing: BTreeSet<String> =
+                    content.lines().map(|l| l.trim().to_string()).collect();
+                let missing: Vec<&String> = patterns
+                    .iter()
+                    .filter(|p| !existing.contains(p.trim()))
+                    .collect();
+                if !content.is_empty() && !content.ends_with('\n') && !missing.is_empty() {
+                    content.push('\n');
+                }
+                for pattern in missing {
+                    content.push_str(pattern);
+                    content.push('\n');
                 }
+                Self::write_file(&resolved, &content)?;
+                reporter.emit(&Event::FileWritten { path: ".gitignore" });
+                Ok(())
+            }
+            StepAction::RunCommand(_)
+            | StepAction::InstallPackages { .. }
+            | StepAction::GitInit { .. }
+            | StepAction::GitCommit { .. } => {
+                unreachable!("command steps are handled above")
+            }
+        })
+    }
+
+    #[cfg(unix)]
+    fn set_mode(path: &Path, mode: u32) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to chmod: {}", path.display()))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+        // Only the read-only bit exists here, and plans should not rely on it
+        Ok(())
+    }
+
+    fn write_file(path: &Path, content: &str) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write file: {}", path.display()))
+    }
+
+    /// Apply a mutation, returning what changed, or `None` if it was already in place.
+    fn apply_modification(
+        path: &Path,
+        modification: &FileModification,
+    ) -> Result<Option<AppliedChange>> {
+        let kind = modification.mutation_type.as_str();
+        if TEXT_MUTATIONS.contains(&kind) {
+            let previous = if path.exists() {
+                Some(fs::read_to_string(path)?)
             } else {
-                // If file doesn't exist, create it with the content
-                fs::write(&path, &modification.content)?;
+                None
+            };
+            let edited = apply_text(
+                kind,
+                &modification.path,
+                previous.as_deref().unwrap_or_default(),
+                &modification.content,
+            )
+            .with_context(|| format!("Failed to apply {} to {}", kind, modification.path))?;
+            let Some(edited) = edited else {
+                return Ok(None);
+            };
+            Self::write_file(path, &edited)?;
+            return Ok(Some(match previous {
+                Some(previous) => AppliedChange::text_edited(modification, &previous, &edited),
+                None => AppliedChange::FileCreated {
+                    path: modification.path.clone(),
+                    sha256: sha256_hex(edited.as_bytes()),
+                },
+            }));
+        }
+        if KEY_VALUE_MUTATIONS.contains(&kind) {
+            let existed = path.exists();
+            let previous = if existed {
+                fs::read_to_string(path)?
+            } else {
+                String::new()
+            };
+            let edit = keyvalue::apply(kind, &modification.path, &previous, &modification.content)
+                .with_context(|| format!("Failed to apply {} to {}", kind, modification.path))?;
+            let Some(edit) = edit else {
+                return Ok(None);
+            };
+            Self::write_file(path, &edit.text)?;
+            if !existed {
+                return Ok(Some(AppliedChange::FileCreated {
+                    path: modification.path.clone(),
+                    sha256: sha256_hex(edit.text.as_bytes()),
+                }));
+            }
+            return Ok(Some(AppliedChange::KeysSet {
+                path: modification.path.clone(),
+                mutation_type: modification.mutation_type.clone(),
+                content: modification.content.clone(),
+                section: edit.section,
+                added: edit.added,
+                replaced: edit.replaced,
+                previous: edit.previous,
+            }));
+        }
+        if !path.exists() {
+            if kind == "json_patch" {
+                bail!(
+                    "Cannot apply json_patch to {}: the file does not exist",
+                    modification.path
+                );
+            }
+            // If file doesn't exist, create it with the content
+            Self::write_file(path, &modification.content)?;
+            return Ok(Some(AppliedChange::FileCreated {
+                path: modification.path.clone(),
+                sha256: sha256_hex(modification.content.as_bytes()),
+            }));
+        }
+        let mut content = fs::read_to_string(path)?;
+        match modification.mutation_type.as_str() {
+            "text_append" => {
+                content.push_str(&modification.content);
+                fs::write(path, content)?;
+                Ok(Some(AppliedChange::TextAppended {
+                    path: modification.path.clone(),
+                    content: modification.content.clone(),
+                }))
+            }
+            "json_merge" => {
+                let mut base: Value = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse JSON: {}", path.display()))?;
+                let patch: Value =
+                    serde_json::from_str(&modification.content).with_context(|| {
+                        format!("Invalid json_merge content for {}", path.display())
+                    })?;
+                let previous = previous_values(&base, &patch);
+                json_merge(&mut base, patch.clone());
+                fs::write(path, json_like(&content, &base)?)?;
+                Ok(Some(AppliedChange::JsonMerged {
+                    path: modification.path.clone(),
+                    patch,
+                    previous,
+                }))
+            }
+            "json_patch" => {
+                let mut document: Value = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse JSON: {}", path.display()))?;
+                let operations: json_patch::Patch = serde_json::from_str(&modification.content)
+                    .with_context(|| {
+                        format!("Invalid json_patch content for {}", path.display())
+                    })?;
+                // A patch is not idempotent by itself, so skip it once its effect is in place
+                if json_patch_present(
+                    &document,
+                    &serde_json::from_str::<Vec<Value>>(&modification.content)?,
+                ) {
+                    return Ok(None);
+                }
+                // All operations apply or none do, so a failed `test` leaves the file as it was
+                json_patch::patch(&mut document, &operations)
+                    .with_context(|| format!("Failed to apply json_patch to {}", path.display()))?;
+                let patched = json_like(&content, &document)?;
+                if patched == content {
+                    return Ok(None);
+                }
+                fs::write(path, &patched)?;
+                Ok(Some(AppliedChange::text_edited(
+                    modification,
+                    &content,
+                    &patched,
+                )))
             }
+            other => bail!("Unknown mutation type: {}", other),
         }
+    }
+}
 
-        // 5. Add scripts to package.json? Not implemented.
+impl Workspace {
+    fn record(&self, step: &Step, change: AppliedChange) {
+        self.changes.lock().unwrap().push((step.id.clone(), change));
+    }
 
-        Ok(())
+    /// The changes made so far. Parallel steps finish in any order, so they are put back
+    /// in plan order.
+    fn applied(&self, steps: &[Step]) -> Applied {
+        let mut changes = self.changes.lock().unwrap().clone();
+        changes.sort_by_key(|(id, _)| steps.iter().position(|s| &s.id == id));
+        Applied {
+            changes: changes.into_iter().map(|(_, change)| change).collect(),
+            conflicts: self.conflicts.lock().unwrap().clone(),
+        }
+    }
+}
+
+fn git(args: Vec<String>) -> CommandStep {
+    CommandStep {
+        command: "git".to_string(),
+        args,
+        env: BTreeMap::new(),
+        cwd: None,
+        timeout_secs: None,
+        creates: Vec::new(),
+    }
+}
+
+/// Whether `root` or one of its ancestors is a git work tree.
+fn repo_state(root: &Path) -> RepoCondition {
+    if root.ancestors().any(|dir| dir.join(".git").exists()) {
+        RepoCondition::ExistingRepo
+    } else {
+        RepoCondition::NewRepo
+    }
+}
+
+/// The values in `base` that merging `patch` will overwrite, shaped like `patch`.
+fn previous_values(base: &Value, patch: &Value) -> Value {
+    let mut previous = serde_json::Map::new();
+    if let (Value::Object(base), Value::Object(patch)) = (base, patch) {
+        for (key, value) in patch {
+            match (base.get(key), value) {
+                (Some(old @ Value::Object(_)), Value::Object(_)) => {
+                    previous.insert(key.clone(), previous_values(old, value));
+                }
+                (Some(old), _) => {
+                    previous.insert(key.clone(), old.clone());
+                }
+                (None, _) => {}
+            }
+        }
+    }
+    Value::Object(previous)
+}
+
+/// Deep-merge `patch` into `base`: objects merge recursively, anything else replaces.
+fn json_merge(base: &mut Value, patch: Value) {
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => {
+            for (key, value) in patch {
+                json_merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+/// `value` as pretty JSON laid out like `original`: keys `original` already had keep their
+/// order and the indentation is the same, so rewriting a file only changes what was edited.
+pub fn json_like(original: &str, value: &Value) -> Result<String> {
+    let mut value = value.clone();
+    if let Ok(before) = serde_json::from_str::<Value>(original) {
+        keep_order(&before, &mut value);
+    }
+    let indent = original
+        .lines()
+        .find_map(|line| {
+            let trimmed = line.trim_start_matches([' ', '\t']);
+            (!trimmed.is_empty() && trimmed.len() < line.len())
+                .then(|| &line[..line.len() - trimmed.len()])
+        })
+        .unwrap_or("  ");
+    let mut out = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    value.serialize(&mut serde_json::Serializer::with_formatter(
+        &mut out, formatter,
+    ))?;
+    Ok(String::from_utf8(out)? + "\n")
+}
+
+/// Reorder the object keys of `after` to follow `before`, with new keys last.
+fn keep_order(before: &Value, after: &mut Value) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            let mut ordered = serde_json::Map::new();
+            for key in before.keys() {
+                if let Some(value) = after.shift_remove(key) {
+                    ordered.insert(key.clone(), value);
+                }
+            }
+            ordered.append(after);
+            for (key, value) in ordered.iter_mut() {
+                if let Some(old) = before.get(key) {
+                    keep_order(old, value);
+                }
+            }
+            *after = ordered;
+        }
+        (Value::Array(before), Value::Array(after)) => {
+            for (old, value) in before.iter().zip(after.iter_mut()) {
+                keep_order(old, value);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::logging::{OutputFormat, Verbosity};
+    use crate::policy::Confirmation;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn plan_with_steps(steps: Value) -> ExecutionPlan {
+        serde_json::from_value(json!({
+            "plan_version": 2,
+            "steps": steps,
+            "scaffold": null,
+            "dependencies": [],
+            "dev_dependencies": [],
+            "file_writes": [],
+            "file_modifications": [],
+            "scripts": []
+        }))
+        .unwrap()
+    }
+
+    fn sh(id: &str, script: &str) -> Value {
+        json!({"id": id, "phase": "post_install", "type": "run_command", "command": "sh", "args": ["-c", script]})
+    }
+
+    fn sh_policy() -> ExecutionPolicy {
+        let mut policy = ExecutionPolicy::new(Confirmation::AssumeYes);
+        policy.allow_binary("sh");
+        policy
+    }
+
+    #[test]
+    fn test_parallel_log_follows_plan_order() {
+        let dir = tempdir().unwrap();
+        let plan = plan_with_steps(json!([
+            sh("slow", "sleep 0.3; echo slow-done"),
+            sh("fast", "echo fast-done"),
+            {"id": "write", "phase": "files", "type": "write_file", "path": "a.txt", "content": "a",
+             "depends_on": ["slow", "fast"]},
+        ]));
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        Executor::execute(
+            &plan,
+            dir.path(),
+            &sh_policy(),
+            &reporter,
+            &ExecuteOptions {
+                jobs: 2,
+                ..ExecuteOptions::default()
+            },
+        )
+        .unwrap();
+
+        let log = fs::read_to_string(reporter.path().unwrap()).unwrap();
+        let slow = log.find("slow-done").unwrap();
+        let fast = log.find("fast-done").unwrap();
+        assert!(slow < fast);
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt")).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_parallel_failures_are_aggregated() {
+        let dir = tempdir().unwrap();
+        let plan = plan_with_steps(json!([
+            sh("one", "exit 1"),
+            sh("two", "exit 2"),
+            {"id": "after", "phase": "files", "type": "create_dir", "path": "never",
+             "depends_on": ["one"]},
+        ]));
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        let err = Executor::execute(
+            &plan,
+            dir.path(),
+            &sh_policy(),
+            &reporter,
+            &ExecuteOptions {
+                jobs: 2,
+                ..ExecuteOptions::default()
+            },
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("2 steps failed:"));
+        assert!(err.contains("\n  - one: Step 'one' exited with"));
+        assert!(err.contains("\n  - two: Step 'two' exited with"));
+        assert!(err.ends_with("Not started: after"));
+        assert!(!dir.path().join("never").exists());
+    }
+
+    #[test]
+    fn test_verify_runs_every_check() {
+        let dir = tempdir().unwrap();
+        let check = |id: &str, script: &str| {
+            let mut step = sh(id, script);
+            step["phase"] = json!("verify");
+            step
+        };
+        let plan = plan_with_steps(json!([
+            sh("setup", "true"),
+            check("lint", "exit 1"),
+            check("build", "touch built"),
+        ]));
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        let err = Executor::execute(
+            &plan,
+            dir.path(),
+            &sh_policy(),
+            &reporter,
+            &ExecuteOptions::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("Verification failed: lint "));
+        assert!(dir.path().join("built").exists());
+        let log = fs::read_to_string(reporter.path().unwrap()).unwrap();
+        assert!(log.contains("Verification: 1 passed, 1 failed"));
+
+        let skip = ExecuteOptions {
+            skip_verify: true,
+            ..ExecuteOptions::default()
+        };
+        Executor::execute(&plan, dir.path(), &sh_policy(), &reporter, &skip).unwrap();
+    }
+
+    #[test]
+    fn test_repo_conditions_and_gitignore() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(".gitignore"), "node_modules/").unwrap();
+        let mut existing_only = sh("existing-only", "touch branched");
+        existing_only["when"] = json!("existing_repo");
+        let plan = plan_with_steps(json!([
+            existing_only,
+            {"id": "gitignore", "phase": "files", "type": "git_ignore", "patterns": ["node_modules/", "dist/"]},
+        ]));
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        Executor::execute(
+            &plan,
+            dir.path(),
+            &sh_policy(),
+            &reporter,
+            &ExecuteOptions::default(),
+        )
+        .unwrap();
+
+        assert!(!dir.path().join("branched").exists());
+        let gitignore = fs::read_to_string(dir.path().join(".gitignore")).unwrap();
+        assert_eq!(gitignore, "node_modules/\ndist/\n");
+    }
+
+    #[test]
+    fn test_commit_takes_only_the_plan_paths() {
+        let dir = tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+            String::from_utf8(output.stdout).unwrap()
+        };
+        git(&["init", "-q"]);
+        fs::write(dir.path().join("staged.txt"), "mine").unwrap();
+        git(&["add", "staged.txt"]);
+        let plan = plan_with_steps(json!([
+            {"id": "write", "phase": "files", "type": "write_file", "path": "a.txt", "content": "a"},
+            {"id": "git-commit", "phase": "post_apply", "type": "git_commit", "message": "Set up",
+             "author": {"name": "Test", "email": "test@example.com"}, "paths": ["a.txt", "lock.txt", "never-written.txt"]},
+        ]));
+        let mut policy = ExecutionPolicy::new(Confirmation::AssumeYes);
+        policy.allow_binary("git");
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        let write_lock = |_: &Applied| Ok(fs::write(dir.path().join("lock.txt"), "locked")?);
+        let options = ExecuteOptions {
+            before_commit: Some(&write_lock),
+            ..ExecuteOptions::default()
+        };
+        Executor::execute(&plan, dir.path(), &policy, &reporter, &options).unwrap();
+
+        assert_eq!(
+            git(&["show", "--name-only", "--format=", "HEAD"]),
+            "a.txt\nlock.txt\n"
+        );
+        assert_eq!(git(&["diff", "--cached", "--name-only"]), "staged.txt\n");
+        // Nothing changed, so applying again must not fail on an empty commit
+        Executor::execute(&plan, dir.path(), &policy, &reporter, &options).unwrap();
+        assert_eq!(git(&["rev-list", "--count", "HEAD"]), "1\n");
+
+        // A deleted file is gone from disk but still tracked, so its deletion is committed
+        let removal = plan_with_steps(json!([
+            {"id": "delete", "phase": "files", "type": "delete", "path": "a.txt"},
+            {"id": "git-commit", "phase": "post_apply", "type": "git_commit", "message": "Remove",
+             "author": {"name": "Test", "email": "test@example.com"}, "paths": ["a.txt", "never-written.txt"]},
+        ]));
+        Executor::execute(
+            &removal,
+            dir.path(),
+            &policy,
+            &reporter,
+            &ExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            git(&["show", "--name-status", "--format=", "HEAD"]),
+            "D\ta.txt\n"
+        );
+    }
+
+    #[test]
+    fn test_json_patch_is_atomic() {
+        let dir = tempdir().unwrap();
+        let package = "{\n    \"name\": \"app\",\n    \"version\": \"1.0.0\",\n    \"scripts\": {\n        \"test\": \"echo none\",\n        \"build\": \"vite build\"\n    },\n    \"private\": true,\n    \"files\": [\n        \"old\",\n        \"dist\"\n    ]\n}\n";
+        fs::write(dir.path().join("package.json"), package).unwrap();
+        let patch = |ops: Value| {
+            plan_with_steps(json!([
+                {"id": "patch", "phase": "modify", "type": "modify_file", "path": "package.json",
+                 "mutation_type": "json_patch", "content": ops.to_string()},
+            ]))
+        };
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        let options = ExecuteOptions::default();
+
+        // A failing `test` guard leaves the file untouched, including the earlier operation
+        let guarded = patch(json!([
+            {"op": "remove", "path": "/scripts/test"},
+            {"op": "test", "path": "/name", "value": "other"},
+        ]));
+        Executor::execute(&guarded, dir.path(), &sh_policy(), &reporter, &options).unwrap_err();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            package
+        );
+        // Without a test, a second run would remove the element after it
+        let unguarded = patch(json!([{"op": "remove", "path": "/files/0"}]));
+        let err = Executor::execute(&unguarded, dir.path(), &sh_policy(), &reporter, &options)
+            .unwrap_err();
+        assert!(err.to_string().contains("without a test on it first"));
+
+        let ops = patch(json!([
+            {"op": "test", "path": "/name", "value": "app"},
+            {"op": "move", "from": "/scripts/test", "path": "/scripts/check"},
+            {"op": "add", "path": "/keywords", "value": ["b"]},
+            {"op": "add", "path": "/keywords/0", "value": "a"},
+            {"op": "remove", "path": "/version"},
+            {"op": "test", "path": "/files/0", "value": "old"},
+            {"op": "remove", "path": "/files/0"},
+        ]));
+        let applied =
+            Executor::execute(&ops, dir.path(), &sh_policy(), &reporter, &options).unwrap();
+        assert_eq!(applied.changes.len(), 1);
+        // Untouched keys keep their order and the file keeps its indentation
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            "{\n    \"name\": \"app\",\n    \"scripts\": {\n        \"build\": \"vite build\",\n        \"check\": \"echo none\"\n    },\n    \"private\": true,\n    \"files\": [\n        \"dist\"\n    ],\n    \"keywords\": [\n        \"a\",\n        \"b\"\n    ]\n}\n"
+        );
+        // Applying it again finds every operation in place instead of adding "a" twice
+        let patched = fs::read_to_string(dir.path().join("package.json")).unwrap();
+        let applied =
+            Executor::execute(&ops, dir.path(), &sh_policy(), &reporter, &options).unwrap();
+        assert!(applied.changes.is_empty());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("package.json")).unwrap(),
+            patched
+        );
+    }
+
+    #[test]
+    fn test_write_merges_with_user_edits() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("config.js"), "a\nb (user)\nc\nd\n").unwrap();
+        let plan = plan_with_steps(json!([
+            {"id": "write", "phase": "files", "type": "write_file", "path": "config.js", "content": "a\nb\nc\nd (new)\n"},
+        ]));
+        let options = ExecuteOptions {
+            merge_bases: [("config.js".to_string(), "a\nb\nc\nd\n".to_string())].into(),
+            ..Default::default()
+        };
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        let applied =
+            Executor::execute(&plan, dir.path(), &sh_policy(), &reporter, &options).unwrap();
+
+        assert!(applied.conflicts.is_empty());
+        let merged = fs::read_to_string(dir.path().join("config.js")).unwrap();
+        assert_eq!(merged, "a\nb (user)\nc\nd (new)\n");
+
+        // The generated content is kept as the base for the next write
+        let plan = plan_with_steps(json!([
+            {"id": "write", "phase": "files", "type": "write_file", "path": "config.js", "content": "a\nb (next)\nc\nd (new)\n"},
+        ]));
+        let applied = Executor::execute(
+            &plan,
+            dir.path(),
+            &sh_policy(),
+            &reporter,
+            &ExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(applied.conflicts, vec!["config.js"]);
+        let merged = fs::read_to_string(dir.path().join("config.js")).unwrap();
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< yours\nb (user)\n=======\nb (next)\n>>>>>>> generated\nc\nd (new)\n"
+        );
+        let base = fs::read_to_string(dir.path().join(".orchestrator/base/config.js")).unwrap();
+        assert_eq!(base, "a\nb (next)\nc\nd (new)\n");
     }
 }
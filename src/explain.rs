@@ -0,0 +1,91 @@
+use crate::plan::{ExecutionPlan, ItemKind, Provenance};
+use anyhow::{bail, Result};
+use std::fmt::Write;
+
+/// Render the resolution chain for every plan item, or only those named `item`.
+pub fn explain(plan: &ExecutionPlan, item: Option<&str>) -> Result<String> {
+    let provenance = match &plan.provenance {
+        Some(p) => p,
+        None => bail!("Plan has no provenance information"),
+    };
+
+    let entries: Vec<&Provenance> = provenance
+        .iter()
+        .filter(|p| item.is_none_or(|name| p.item == name))
+        .collect();
+    if let Some(name) = item {
+        if entries.is_empty() {
+            bail!("No plan item named '{}'", name);
+        }
+    }
+
+    let mut out = String::new();
+    for p in entries {
+        writeln!(out, "{} ({})", p.item, kind_label(p.kind))?;
+        if p.layer == "spec" {
+            writeln!(out, "  <- {} declared in spec", p.table)?;
+            continue;
+        }
+        match &p.feature_id {
+            Some(feature) => writeln!(out, "  <- feature '{}' requested in spec", feature)?,
+            None if p.kind == ItemKind::Scaffold => writeln!(out, "  <- framework base scaffold")?,
+            None => writeln!(out, "  <- always included for the framework")?,
+        }
+        let scope = match &p.framework_id {
+            Some(fw) => format!("framework-specific: {}", fw),
+            None => "global".to_string(),
+        };
+        match p.row_id {
+            Some(id) => writeln!(out, "  <- {} row #{} ({})", p.table, id, scope)?,
+            None => writeln!(out, "  <- {} row ({})", p.table, scope)?,
+        }
+        writeln!(out, "  <- registry layer '{}'", p.layer)?;
+    }
+    Ok(out)
+}
+
+fn kind_label(kind: ItemKind) -> &'static str {
+    match kind {
+        ItemKind::Scaffold => "scaffold",
+        ItemKind::Dependency => "dependency",
+        ItemKind::DevDependency => "dev dependency",
+        ItemKind::FileModification => "file modification",
+        ItemKind::Step => "step",
+        ItemKind::Hook => "hook",
+        ItemKind::GitignorePattern => "gitignore pattern",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::registry::sqlite::SqliteRegistry;
+    use crate::spec::ProjectSpec;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_explain_single_item() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+        let spec = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec!["tailwind".to_string(), "eslint".to_string()],
+            hooks: Default::default(),
+            git: None,
+        };
+        let plan = Engine::resolve(&spec, &registry).unwrap();
+
+        let out = explain(&plan, Some("tailwindcss")).unwrap();
+        assert!(out.contains("tailwindcss (dev dependency)"));
+        assert!(out.contains("feature 'tailwind'"));
+        assert!(out.contains("framework-specific: react"));
+
+        let out = explain(&plan, Some("eslint")).unwrap();
+        assert!(out.contains("(global)"));
+
+        assert!(explain(&plan, Some("left-pad")).is_err());
+    }
+}
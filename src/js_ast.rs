@@ -0,0 +1,416 @@
+use anyhow::{bail, Context, Result};
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{
+    ArrayExpression, AssignmentTarget, Expression, ImportDeclarationSpecifier, ObjectExpression,
+    ObjectPropertyKind, Program, Statement,
+};
+use oxc_parser::Parser;
+use oxc_span::{GetSpan, SourceType, Span};
+use serde::Deserialize;
+
+/// Content of a `js_ast` mutation.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsMutation {
+    /// Import declarations to add unless the file already has them, e.g.
+    /// `import tailwindcss from '@tailwindcss/vite'`.
+    #[serde(default)]
+    imports: Vec<String>,
+    /// Entries to add to arrays or objects in the exported config.
+    #[serde(default)]
+    insert: Vec<Insert>,
+}
+
+/// An entry added at `path`, such as `default.plugins`. `default` is the default export
+/// (or `module.exports`), looking through a wrapping call like `defineConfig({...})`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Insert {
+    path: String,
+    /// With a key, `path` names an object that gets a `key: value` property. Without one,
+    /// it names an array that gets `value` appended, and is created if missing.
+    key: Option<String>,
+    /// Source text of the expression to add.
+    value: String,
+}
+
+/// Apply a `js_ast` mutation to the JavaScript or TypeScript source of `file`, or return
+/// `None` when every import and entry is already there.
+///
+/// Each change is a text insertion at a position found in the parsed tree, so the rest
+/// of the file keeps its formatting.
+pub fn apply(file: &str, source: &str, content: &str) -> Result<Option<String>> {
+    let mutation: JsMutation = serde_json::from_str(content)
+        .context("Invalid js_ast content: expected {imports, insert}")?;
+    let source_type = SourceType::from_path(file).unwrap_or_else(|_| SourceType::mjs());
+    let mut text = source.to_string();
+    let mut changed = false;
+    for import in &mutation.imports {
+        if let Some(edit) = with_program(&text, source_type, |program| add_import(program, import))?
+        {
+            edit.apply(&mut text);
+            changed = true;
+        }
+    }
+    for insert in &mutation.insert {
+        if let Some(edit) = with_program(&text, source_type, |program| add_entry(program, insert))?
+        {
+            edit.apply(&mut text);
+            changed = true;
+        }
+    }
+    if !changed {
+        return Ok(None);
+    }
+    // The added text comes from the registry as it is, so check the result still parses
+    with_program(&text, source_type, |_| Ok(()))
+        .with_context(|| format!("js_ast would leave {} with invalid code", file))?;
+    Ok(Some(text))
+}
+
+/// Text put in place of `remove` bytes at a byte offset of the source.
+struct Edit {
+    at: usize,
+    remove: usize,
+    text: String,
+}
+
+impl Edit {
+    fn insert(at: usize, text: String) -> Self {
+        Edit {
+            at,
+            remove: 0,
+            text,
+        }
+    }
+
+    fn apply(self, source: &mut String) {
+        source.replace_range(self.at..self.at + self.remove, &self.text);
+    }
+}
+
+fn with_program<T>(
+    source: &str,
+    source_type: SourceType,
+    f: impl FnOnce(&Program) -> Result<T>,
+) -> Result<T> {
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, source, source_type).parse();
+    if let Some(error) = parsed.errors.first() {
+        bail!("Failed to parse: {}", error.message);
+    }
+    f(&parsed.program)
+}
+
+/// The module an import declaration reads from and the local names it binds.
+fn import_bindings(program: &Program) -> Vec<(String, Vec<String>, Span)> {
+    program
+        .body
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::ImportDeclaration(decl) => {
+                let locals = decl
+                    .specifiers
+                    .iter()
+                    .flatten()
+                    .map(|specifier| match specifier {
+                        ImportDeclarationSpecifier::ImportSpecifier(s) => s.local.name.to_string(),
+                        ImportDeclarationSpecifier::ImportDefaultSpecifier(s) => {
+                            s.local.name.to_string()
+                        }
+                        ImportDeclarationSpecifier::ImportNamespaceSpecifier(s) => {
+                            s.local.name.to_string()
+                        }
+                    })
+                    .collect();
+                Some((decl.source.value.to_string(), locals, decl.span))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn add_import(program: &Program, import: &str) -> Result<Option<Edit>> {
+    let wanted = with_program(import, SourceType::mjs(), |parsed| {
+        Ok(import_bindings(parsed))
+    })?;
+    let [(module, names, _)] = wanted.as_slice() else {
+        bail!("'{}' is not a single import declaration", import);
+    };
+    let existing = import_bindings(program);
+    for name in names {
+        if let Some((other, _, _)) = existing
+            .iter()
+            .find(|(m, locals, _)| m != module && locals.contains(name))
+        {
+            bail!("'{}' is already imported from '{}'", name, other);
+        }
+    }
+    let present = existing
+        .iter()
+        .any(|(m, locals, _)| m == module && names.iter().all(|name| locals.contains(name)));
+    if present {
+        return Ok(None);
+    }
+    // Follow the file's use of semicolons
+    let statement = import.trim().trim_end_matches(';');
+    let semicolons = match existing.last() {
+        Some((_, _, span)) => span.source_text(program.source_text).ends_with(';'),
+        None => program.source_text.contains(";\n"),
+    };
+    let terminator = if semicolons { ";" } else { "" };
+    Ok(Some(match existing.last() {
+        Some((_, _, span)) => {
+            Edit::insert(span.end as usize, format!("\n{}{}", statement, terminator))
+        }
+        None => Edit::insert(0, format!("{}{}\n", statement, terminator)),
+    }))
+}
+
+fn add_entry(program: &Program, insert: &Insert) -> Result<Option<Edit>> {
+    let segments: Vec<&str> = insert.path.split('.').collect();
+    if segments[0] != "default" {
+        bail!("Path '{}' must start at 'default'", insert.path);
+    }
+    let mut object = match default_export(program) {
+        Some(object) => object,
+        None => bail!("No exported config object to insert '{}' into", insert.path),
+    };
+    let source = program.source_text;
+    let (parents, last) = segments[1..].split_at(segments.len().saturating_sub(2));
+    for segment in parents {
+        object = match property(object, segment).and_then(config_object) {
+            Some(inner) => inner,
+            None => bail!("'{}' has no object '{}'", insert.path, segment),
+        };
+    }
+
+    match (&insert.key, last.first()) {
+        (Some(key), _) => {
+            // The whole path names the object that gets the property
+            if let Some(segment) = last.first() {
+                object = match property(object, segment).and_then(config_object) {
+                    Some(inner) => inner,
+                    None => bail!("'{}' has no object '{}'", insert.path, segment),
+                };
+            }
+            if property(object, key).is_some() {
+                return Ok(None);
+            }
+            let entry = format!("{}: {}", key, insert.value);
+            Ok(Some(append(source, object.span, last_span(object), &entry)))
+        }
+        (None, Some(segment)) => match property(object, segment) {
+            Some(Expression::ArrayExpression(array)) => {
+                if contains_element(source, array, &insert.value) {
+                    return Ok(None);
+                }
+                let last = array.elements.last().map(GetSpan::span);
+                Ok(Some(append(source, array.span, last, &insert.value)))
+            }
+            Some(_) => bail!("'{}' is not an array", insert.path),
+            None => {
+                let entry = format!("{}: [{}]", segment, insert.value);
+                Ok(Some(append(source, object.span, last_span(object), &entry)))
+            }
+        },
+        (None, None) => bail!("Path '{}' needs a property after 'default'", insert.path),
+    }
+}
+
+/// The object exported by `export default` or assigned to `module.exports`.
+fn default_export<'b, 'a>(program: &'b Program<'a>) -> Option<&'b ObjectExpression<'a>> {
+    program.body.iter().find_map(|statement| match statement {
+        Statement::ExportDefaultDeclaration(decl) => decl.declaration.as_expression().and_then(config_object),
+        Statement::ExpressionStatement(statement) => match &statement.expression {
+            Expression::AssignmentExpression(assign) => match &assign.left {
+                AssignmentTarget::StaticMemberExpression(member)
+                    if member.property.name == "exports"
+                        && matches!(&member.object, Expression::Identifier(id) if id.name == "module") =>
+                {
+                    config_object(&assign.right)
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// An object literal, looking through parentheses, type assertions and a config helper
+/// call such as `defineConfig({...})`.
+fn config_object<'b, 'a>(expression: &'b Expression<'a>) -> Option<&'b ObjectExpression<'a>> {
+    match expression {
+        Expression::ObjectExpression(object) => Some(object),
+        Expression::CallExpression(call) => call
+            .arguments
+            .first()?
+            .as_expression()
+            .and_then(config_object),
+        Expression::ParenthesizedExpression(inner) => config_object(&inner.expression),
+        Expression::TSSatisfiesExpression(inner) => config_object(&inner.expression),
+        Expression::TSAsExpression(inner) => config_object(&inner.expression),
+        _ => None,
+    }
+}
+
+fn property<'b, 'a>(object: &'b ObjectExpression<'a>, name: &str) -> Option<&'b Expression<'a>> {
+    object.properties.iter().find_map(|kind| match kind {
+        ObjectPropertyKind::ObjectProperty(p) if p.key.static_name().as_deref() == Some(name) => {
+            Some(&p.value)
+        }
+        _ => None,
+    })
+}
+
+fn last_span(object: &ObjectExpression) -> Option<Span> {
+    object.properties.last().map(GetSpan::span)
+}
+
+fn contains_element(source: &str, array: &ArrayExpression, value: &str) -> bool {
+    let normalize = |s: &str| s.split_whitespace().collect::<String>();
+    array
+        .elements
+        .iter()
+        .any(|element| normalize(element.span().source_text(source)) == normalize(value))
+}
+
+/// Insert `entry` as the last item of the bracketed list at `list`, following its layout:
+/// on a line of its own in a multi-line list, inline otherwise, and keeping a trailing comma.
+fn append(source: &str, list: Span, last: Option<Span>, entry: &str) -> Edit {
+    let open = list.start as usize + 1;
+    let close = list.end as usize - 1;
+    let Some(last) = last else {
+        let inner = &source[open..close];
+        if !inner.trim().is_empty() {
+            // Only comments: keep them and add the entry after them
+            let at = open + inner.trim_end().len();
+            let text = if inner.contains('\n') {
+                format!("\n{}{}", indentation(source, at), entry)
+            } else {
+                format!(" {}", entry)
+            };
+            return Edit::insert(at, text);
+        }
+        let text = if source[..open].ends_with('{') {
+            format!(" {} ", entry)
+        } else {
+            entry.to_string()
+        };
+        return Edit {
+            at: open,
+            remove: close - open,
+            text,
+        };
+    };
+    let after = &source[last.end as usize..close];
+    let comma = after.find(',').filter(|i| after[..*i].trim().is_empty());
+    let multiline = source[list.start as usize..last.start as usize].contains('\n');
+    let at = match comma {
+        Some(i) => last.end as usize + i + 1,
+        None => last.end as usize,
+    };
+    let text = match (multiline, comma.is_some()) {
+        (true, true) => format!("\n{}{},", indentation(source, last.start as usize), entry),
+        (true, false) => format!(",\n{}{}", indentation(source, last.start as usize), entry),
+        (false, true) => format!(" {},", entry),
+        (false, false) => format!(", {}", entry),
+    };
+    Edit::insert(at, text)
+}
+
+/// The whitespace before the line holding `offset`.
+fn indentation(source: &str, offset: usize) -> &str {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &source[line_start..];
+    &line[..line.len() - line.trim_start().len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VITE_CONFIG: &str = "import { defineConfig } from 'vite'
+import react from '@vitejs/plugin-react'
+
+// https://vite.dev/config/
+export default defineConfig({
+  plugins: [
+    react(),
+  ],
+})
+";
+
+    #[test]
+    fn test_js_ast_adds_import_and_plugin() {
+        let content = r#"{
+            "imports": ["import tailwindcss from '@tailwindcss/vite'"],
+            "insert": [
+                {"path": "default.plugins", "value": "tailwindcss()"},
+                {"path": "default.server", "value": "{ port: 3000 }", "key": "hmr"},
+                {"path": "default", "key": "base", "value": "'/app/'"}
+            ]
+        }"#;
+        let err = apply("vite.config.js", VITE_CONFIG, content).unwrap_err();
+        assert_eq!(err.to_string(), "'default.server' has no object 'server'");
+
+        let content = r#"{
+            "imports": ["import tailwindcss from '@tailwindcss/vite'"],
+            "insert": [
+                {"path": "default.plugins", "value": "tailwindcss()"},
+                {"path": "default", "key": "base", "value": "'/app/'"}
+            ]
+        }"#;
+        let updated = apply("vite.config.ts", VITE_CONFIG, content)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            updated,
+            "import { defineConfig } from 'vite'
+import react from '@vitejs/plugin-react'
+import tailwindcss from '@tailwindcss/vite'
+
+// https://vite.dev/config/
+export default defineConfig({
+  plugins: [
+    react(),
+    tailwindcss(),
+  ],
+  base: '/app/',
+})
+"
+        );
+        assert_eq!(apply("vite.config.ts", &updated, content).unwrap(), None);
+    }
+
+    #[test]
+    fn test_js_ast_creates_missing_array_inline() {
+        let source = "module.exports = { content: [] };\n";
+        let content = r#"{"insert": [
+            {"path": "default.content", "value": "'./src/**/*.jsx'"},
+            {"path": "default.plugins", "value": "require('x')"}
+        ]}"#;
+        let updated = apply("tailwind.config.cjs", source, content)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            updated,
+            "module.exports = { content: ['./src/**/*.jsx'], plugins: [require('x')] };\n"
+        );
+
+        // Comments in an empty list stay, and the entry goes after them
+        let source = "module.exports = {\n  plugins: [\n    // add plugins here\n  ],\n};\n";
+        let content = r#"{"insert": [{"path": "default.plugins", "value": "require('x')"}]}"#;
+        assert_eq!(
+            apply("tailwind.config.cjs", source, content).unwrap().unwrap(),
+            "module.exports = {\n  plugins: [\n    // add plugins here\n    require('x')\n  ],\n};\n"
+        );
+        let broken = r#"{"insert": [{"path": "default.plugins", "value": "require('x'"}]}"#;
+        let err = apply("tailwind.config.cjs", source, broken).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "js_ast would leave tailwind.config.cjs with invalid code"
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Mutation types that set keys in `.env` or INI-style files (`.npmrc`, `.editorconfig`).
+pub const KEY_VALUE_MUTATIONS: &[&str] = &["env_set", "ini_set"];
+
+/// Content of an `env_set`/`ini_set` mutation.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyValues {
+    /// `ini_set` only: the section holding the keys, e.g. `*` for `[*]`. Without one,
+    /// keys go before the first section.
+    section: Option<String>,
+    values: BTreeMap<String, Setting>,
+    /// Leave keys that already exist alone instead of updating their value.
+    #[serde(default)]
+    if_absent: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Setting {
+    Value(String),
+    /// A placeholder secret of this many random bytes, hex-encoded, or `PLACEHOLDER` in a
+    /// file that is committed. Never overwrites an existing value, so re-applying keeps
+    /// the secret stable.
+    Secret {
+        secret: usize,
+    },
+}
+
+/// Keys set by an `env_set`/`ini_set` mutation, along with the edited file.
+#[derive(Debug, PartialEq)]
+pub struct KeyValueEdit {
+    pub text: String,
+    pub section: Option<String>,
+    /// Keys that were not in the file before.
+    pub added: Vec<String>,
+    /// Keys whose existing value was overwritten.
+    pub replaced: Vec<String>,
+    /// The overwritten values, except in git-ignored env files where they may be secrets.
+    pub previous: BTreeMap<String, String>,
+}
+
+/// What a secret becomes in a committed file such as `.env.example`.
+const PLACEHOLDER: &str = "change-me";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Syntax {
+    Env,
+    Ini,
+}
+
+impl Syntax {
+    fn of(mutation_type: &str) -> Result<Self> {
+        match mutation_type {
+            "env_set" => Ok(Syntax::Env),
+            "ini_set" => Ok(Syntax::Ini),
+            other => bail!("Unknown key/value mutation type: {}", other),
+        }
+    }
+}
+
+/// Set the keys of an `env_set`/`ini_set` mutation in `contents`, or return `None` when
+/// they all hold their value already.
+///
+/// Existing keys are updated in place, keeping their line's layout, and new keys are
+/// added after the last line of their section. No key is ever written twice. Secrets are
+/// only generated into `path`s the default `.gitignore` patterns keep out of git; other
+/// files get a placeholder in their place.
+pub fn apply(
+    mutation_type: &str,
+    path: &str,
+    contents: &str,
+    content: &str,
+) -> Result<Option<KeyValueEdit>> {
+    let syntax = Syntax::of(mutation_type)?;
+    let spec: KeyValues = serde_json::from_str(content).with_context(|| {
+        format!(
+            "Invalid {} content: expected {{values, if_absent, section}}",
+            mutation_type
+        )
+    })?;
+    if syntax == Syntax::Env && spec.section.is_some() {
+        bail!("env_set does not take a section");
+    }
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    // Follow the file's own `key=value` or `key = value` style
+    let spaced = lines
+        .iter()
+        .find(|line| key_of(syntax, line).is_some())
+        .map_or(syntax == Syntax::Ini, |line| line.contains(" = "));
+
+    let (mut added, mut replaced, mut previous) = (Vec::new(), Vec::new(), BTreeMap::new());
+    for (key, setting) in &spec.values {
+        let range = section_range(syntax, &lines, spec.section.as_deref());
+        let existing: Vec<usize> = range
+            .clone()
+            .into_iter()
+            .flatten()
+            .filter(|i| key_of(syntax, &lines[*i]) == Some(key))
+            .collect();
+        if !existing.is_empty() {
+            let Setting::Value(value) = setting else {
+                continue;
+            };
+            if spec.if_absent {
+                continue;
+            }
+            let mut changed = false;
+            for i in existing {
+                let old = value_of(syntax, &lines[i]);
+                if old != *value {
+                    lines[i] = reassign(syntax, &lines[i], value);
+                    if !changed && !ignored(path) {
+                        previous.insert(key.clone(), old);
+                    }
+                    changed = true;
+                }
+            }
+            if changed {
+                replaced.push(key.clone());
+            }
+            continue;
+        }
+
+        let value = match setting {
+            Setting::Value(value) => value.clone(),
+            Setting::Secret { secret } if ignored(path) => random_hex(*secret)?,
+            Setting::Secret { .. } => PLACEHOLDER.to_string(),
+        };
+        let line = format!(
+            "{}{}{}",
+            key,
+            if spaced { " = " } else { "=" },
+            render(syntax, &value)
+        );
+        match range {
+            Some(range) => {
+                let mut at = range.end;
+                while at > range.start && lines[at - 1].trim().is_empty() {
+                    at -= 1;
+                }
+                lines.insert(at, line);
+            }
+            None => {
+                if lines.last().is_some_and(|last| !last.trim().is_empty()) {
+                    lines.push(String::new());
+                }
+                lines.push(format!("[{}]", spec.section.as_deref().unwrap_or_default()));
+                lines.push(line);
+            }
+        }
+        added.push(key.clone());
+    }
+
+    if added.is_empty() && replaced.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(KeyValueEdit {
+        text: lines.join("\n") + "\n",
+        section: spec.section,
+        added,
+        replaced,
+        previous,
+    }))
+}
+
+/// Whether every key of the mutation is in `contents` with its value, or `None` if the
+/// content cannot be read.
+pub fn present(mutation_type: &str, path: &str, contents: &str, content: &str) -> Option<bool> {
+    apply(mutation_type, path, contents, content)
+        .ok()
+        .map(|edit| edit.is_none())
+}
+
+/// Whether `path` is an env file the default `.gitignore` patterns keep out of git:
+/// `.env`, `.env.local` or `.env.*.local`, in any directory.
+fn ignored(path: &str) -> bool {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    name == ".env" || (name.starts_with(".env.") && name.ends_with(".local"))
+}
+
+/// Remove the lines setting `keys` within `section`, as reverting an `env_set`/`ini_set`
+/// that added them. Returns `None` if none of them are left.
+pub fn remove_keys(
+    mutation_type: &str,
+    contents: &str,
+    section: Option<&str>,
+    keys: &[String],
+) -> Result<Option<String>> {
+    let syntax = Syntax::of(mutation_type)?;
+    let mut lines: Vec<&str> = contents.lines().collect();
+    let Some(range) = section_range(syntax, &lines, section) else {
+        return Ok(None);
+    };
+    let before = lines.len();
+    let mut i = range.start;
+    let mut end = range.end;
+    while i < end {
+        if key_of(syntax, lines[i]).is_some_and(|key| keys.iter().any(|k| k == key)) {
+            lines.remove(i);
+            end -= 1;
+        } else {
+            i += 1;
+        }
+    }
+    if lines.len() == before {
+        return Ok(None);
+    }
+    Ok(Some(if lines.is_empty() {
+        String::new()
+    } else {
+        lines.join("\n") + "\n"
+    }))
+}
+
+/// Put back the `previous` values of keys that still hold what `content` set them to, as
+/// reverting an `env_set`/`ini_set`. Returns the edited text, if anything changed, and the
+/// keys left alone because they were edited since.
+pub fn restore_values(
+    mutation_type: &str,
+    contents: &str,
+    content: &str,
+    section: Option<&str>,
+    previous: &BTreeMap<String, String>,
+) -> Result<(Option<String>, Vec<String>)> {
+    let syntax = Syntax::of(mutation_type)?;
+    let spec: KeyValues = serde_json::from_str(content)
+        .with_context(|| format!("Invalid {} content", mutation_type))?;
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let range = section_range(syntax, &lines, section).unwrap_or_default();
+    let (mut restored, mut kept) = (false, Vec::new());
+    for (key, old) in previous {
+        let at: Vec<usize> = range
+            .clone()
+            .filter(|i| key_of(syntax, &lines[*i]) == Some(key))
+            .collect();
+        let unchanged = match spec.values.get(key) {
+            Some(Setting::Value(value)) => {
+                !at.is_empty() && at.iter().all(|i| value_of(syntax, &lines[*i]) == *value)
+            }
+            _ => false,
+        };
+        if !unchanged {
+            kept.push(key.clone());
+            continue;
+        }
+        for i in at {
+            lines[i] = reassign(syntax, &lines[i], old);
+        }
+        restored = true;
+    }
+    Ok((restored.then(|| lines.join("\n") + "\n"), kept))
+}
+
+/// Indices of the lines in `section`, after its header, or `None` if there is no such
+/// section. `.env` files have no sections, so the whole file is one.
+fn section_range<S: AsRef<str>>(
+    syntax: Syntax,
+    lines: &[S],
+    section: Option<&str>,
+) -> Option<std::ops::Range<usize>> {
+    if syntax == Syntax::Env {
+        return Some(0..lines.len());
+    }
+    let start = match section {
+        None => 0,
+        Some(section) => {
+            lines
+                .iter()
+                .position(|line| header(line.as_ref()) == Some(section))?
+                + 1
+        }
+    };
+    let end = (start..lines.len())
+        .find(|i| header(lines[*i].as_ref()).is_some())
+        .unwrap_or(lines.len());
+    Some(start..end)
+}
+
+/// The section name if `line` is a `[section]` header.
+fn header(line: &str) -> Option<&str> {
+    let line = line.trim();
+    line.strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .map(str::trim)
+}
+
+/// The key `line` assigns, if it is an assignment rather than a comment or header.
+fn key_of(syntax: Syntax, line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with('#')
+        || (syntax == Syntax::Ini && (line.starts_with(';') || line.starts_with('[')))
+    {
+        return None;
+    }
+    let line = match syntax {
+        Syntax::Env => line.strip_prefix("export ").unwrap_or(line).trim_start(),
+        Syntax::Ini => line,
+    };
+    let (key, _) = line.split_once('=')?;
+    Some(key.trim())
+}
+
+/// The value `line` assigns, without `.env` quotes or trailing comment.
+fn value_of(syntax: Syntax, line: &str) -> String {
+    let raw = line.split_once('=').map_or("", |(_, value)| value.trim());
+    if syntax == Syntax::Ini {
+        return raw.to_string();
+    }
+    for quote in ['"', '\''] {
+        if let Some(inner) = raw.strip_prefix(quote).and_then(|r| r.strip_suffix(quote)) {
+            return match quote {
+                '"' => inner.replace("\\\"", "\"").replace("\\\\", "\\"),
+                _ => inner.to_string(),
+            };
+        }
+    }
+    raw.split(" #")
+        .next()
+        .unwrap_or_default()
+        .trim_end()
+        .to_string()
+}
+
+/// `line` with its value replaced, keeping the key, spacing and any trailing comment as
+/// they were.
+fn reassign(syntax: Syntax, line: &str, value: &str) -> String {
+    let (key, rest) = line.split_once('=').unwrap_or((line, ""));
+    let gap = rest.len() - rest.trim_start().len();
+    format!(
+        "{}={}{}{}",
+        key,
+        &rest[..gap],
+        render(syntax, value),
+        comment(syntax, &rest[gap..])
+    )
+}
+
+/// The ` # ...` comment after a `.env` value, with the whitespace before it.
+fn comment(syntax: Syntax, raw: &str) -> &str {
+    if syntax == Syntax::Ini {
+        return "";
+    }
+    let end = match raw.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let mut escaped = false;
+            raw.char_indices().skip(1).find_map(|(i, c)| {
+                let closes = c == quote && !escaped;
+                escaped = quote == '"' && c == '\\' && !escaped;
+                closes.then_some(i + 1)
+            })
+        }
+        _ => raw.find(" #"),
+    };
+    let rest = end.map_or("", |end| &raw[end..]);
+    if rest.trim_start().starts_with('#') {
+        rest
+    } else {
+        ""
+    }
+}
+
+/// A value as written in the file, quoting `.env` values that would not read back as-is.
+fn render(syntax: Syntax, value: &str) -> String {
+    let plain = !value
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '#' | '"' | '\'' | '\\'));
+    if syntax == Syntax::Ini || plain {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// `bytes` random bytes from the operating system, hex-encoded.
+fn random_hex(bytes: usize) -> Result<String> {
+    let mut buf = vec![0u8; bytes];
+    getrandom::getrandom(&mut buf).map_err(|e| anyhow!("Failed to generate a secret: {}", e))?;
+    Ok(buf.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_and_ini_set() {
+        let env =
+            "# Database\nexport DATABASE_URL=postgres://localhost/dev\nPORT=3000 # dev server\n";
+        let content = r#"{"values": {"PORT": "8080", "AUTH_SECRET": {"secret": 16}, "GREETING": "hello world"}}"#;
+        let edit = apply("env_set", ".env", env, content).unwrap().unwrap();
+        assert_eq!(edit.added, ["AUTH_SECRET", "GREETING"]);
+        assert_eq!(edit.replaced, ["PORT"]);
+        let lines: Vec<&str> = edit.text.lines().collect();
+        assert_eq!(
+            &lines[..3],
+            [
+                "# Database",
+                "export DATABASE_URL=postgres://localhost/dev",
+                "PORT=8080 # dev server"
+            ]
+        );
+        assert!(
+            lines[3].starts_with("AUTH_SECRET=") && lines[3].len() == "AUTH_SECRET=".len() + 32
+        );
+        assert_eq!(lines[4], "GREETING=\"hello world\"");
+        // The secret stays as generated and nothing is duplicated
+        assert_eq!(apply("env_set", ".env", &edit.text, content).unwrap(), None);
+        assert_eq!(present("env_set", ".env", &edit.text, content), Some(true));
+        let kept = apply(
+            "env_set",
+            ".env",
+            env,
+            r#"{"values": {"PORT": "8080"}, "if_absent": true}"#,
+        )
+        .unwrap();
+        assert_eq!(kept, None);
+        // The old value of a key in a git-ignored file may be a secret, so it is not kept
+        assert!(edit.previous.is_empty());
+        // Secrets only go into files that stay out of git; others get a placeholder
+        assert!(apply("env_set", "apps/web/.env.development.local", env, content).is_ok());
+        let example = apply("env_set", ".env.example", env, content)
+            .unwrap()
+            .unwrap();
+        assert!(example.text.contains("\nAUTH_SECRET=change-me\n"));
+        assert_eq!(example.previous["PORT"], "3000");
+        let (restored, kept) =
+            restore_values("env_set", &example.text, content, None, &example.previous).unwrap();
+        assert!(kept.is_empty());
+        assert!(restored.unwrap().contains("\nPORT=3000 # dev server\n"));
+
+        let editorconfig = "root = true\n\n[*]\nindent_style = space\n\n[*.md]\ntrim_trailing_whitespace = false\n";
+        let content =
+            r#"{"section": "*", "values": {"indent_size": "2", "indent_style": "space"}}"#;
+        let edit = apply("ini_set", ".editorconfig", editorconfig, content)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            edit.text,
+            "root = true\n\n[*]\nindent_style = space\nindent_size = 2\n\n[*.md]\ntrim_trailing_whitespace = false\n"
+        );
+        let npmrc = apply(
+            "ini_set",
+            ".npmrc",
+            "save-exact=true\n",
+            r#"{"values": {"engine-strict": "true"}}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(npmrc.text, "save-exact=true\nengine-strict=true\n");
+        let new_section = apply(
+            "ini_set",
+            ".editorconfig",
+            "root = true\n",
+            r#"{"section": "Makefile", "values": {"indent_style": "tab"}}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            new_section.text,
+            "root = true\n\n[Makefile]\nindent_style = tab\n"
+        );
+
+        let removed = remove_keys(
+            "ini_set",
+            &edit.text,
+            Some("*"),
+            &["indent_size".to_string()],
+        )
+        .unwrap();
+        assert_eq!(removed.as_deref(), Some(editorconfig));
+    }
+}
@@ -0,0 +1,422 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Directory (relative to the target) that receives per-apply logs.
+pub const LOG_DIR: &str = ".orchestrator/logs";
+
+/// How much is printed to the terminal in text mode. The log file always gets everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors only.
+    Quiet,
+    /// Step headers.
+    Normal,
+    /// Step headers plus live child output.
+    Verbose,
+}
+
+/// What the terminal receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable messages.
+    Text,
+    /// One JSON event per line on stdout.
+    Json,
+}
+
+/// Which stream a captured line came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn as_str(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// Progress events, emitted as newline-delimited JSON in `--format json` mode.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    StepStarted {
+        step: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        argv: Option<&'a [String]>,
+    },
+    StepFinished {
+        step: &'a str,
+        duration_ms: u128,
+        success: bool,
+    },
+    StepSkipped {
+        step: &'a str,
+    },
+    /// A commit step found none of its paths changed.
+    NothingToCommit {
+        step: &'a str,
+    },
+    CommandOutput {
+        step: &'a str,
+        stream: Stream,
+        line: &'a str,
+    },
+    FileWritten {
+        path: &'a str,
+    },
+    MutationApplied {
+        path: &'a str,
+        mutation_type: &'a str,
+    },
+    VerifyResult {
+        step: &'a str,
+        passed: bool,
+    },
+    VerifySummary {
+        passed: usize,
+        failed: usize,
+    },
+    ChangeReverted {
+        path: &'a str,
+    },
+    MergeConflict {
+        path: &'a str,
+        conflicts: usize,
+    },
+    Warning {
+        message: &'a str,
+    },
+    Error {
+        message: &'a str,
+    },
+}
+
+impl Event<'_> {
+    /// Plain-text form used for the log file and text output.
+    fn describe(&self) -> String {
+        match self {
+            Event::StepStarted {
+                step,
+                argv: Some(argv),
+            } => format!("Running {}: {:?}", step, argv),
+            Event::StepStarted { step, argv: None } => format!("Starting {}", step),
+            Event::StepFinished {
+                step,
+                duration_ms,
+                success,
+            } => format!(
+                "{} {} after {}ms",
+                if *success { "Finished" } else { "Failed" },
+                step,
+                duration_ms
+            ),
+            Event::StepSkipped { step } => format!("Skipping {} (condition not met)", step),
+            Event::NothingToCommit { step } => format!("Skipping {} (nothing to commit)", step),
+            Event::CommandOutput { line, .. } => line.to_string(),
+            Event::FileWritten { path } => format!("Wrote {}", path),
+            Event::MutationApplied {
+                path,
+                mutation_type,
+            } => format!("Applied {} to {}", mutation_type, path),
+            Event::VerifyResult { step, passed } => {
+                format!("{} {}", if *passed { "PASS" } else { "FAIL" }, step)
+            }
+            Event::VerifySummary { passed, failed } => {
+                format!("Verification: {} passed, {} failed", passed, failed)
+            }
+            Event::ChangeReverted { path } => format!("Reverted {}", path),
+            Event::MergeConflict { path, conflicts } => format!(
+                "Conflict: {} has {} overlapping change(s) between conflict markers",
+                path, conflicts
+            ),
+            Event::Warning { message } => format!("Warning: {}", message),
+            Event::Error { message } => format!("Error: {}", message),
+        }
+    }
+}
+
+enum Sink {
+    /// Lines recorded before the log file exists.
+    Buffer(Vec<String>),
+    File(File),
+}
+
+/// A line of terminal output.
+enum Output {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Reports progress to the terminal (text or JSON events) and keeps a timestamped log
+/// of everything, shared by all spawned processes.
+pub struct Reporter {
+    verbosity: Verbosity,
+    format: OutputFormat,
+    sink: Mutex<Sink>,
+    path: Mutex<Option<PathBuf>>,
+    /// The step this reporter reports for when steps run concurrently, tagged onto its
+    /// terminal output.
+    step: Option<String>,
+}
+
+impl Reporter {
+    pub fn new(verbosity: Verbosity, format: OutputFormat) -> Self {
+        Reporter {
+            verbosity,
+            format,
+            sink: Mutex::new(Sink::Buffer(Vec::new())),
+            path: Mutex::new(None),
+            step: None,
+        }
+    }
+
+    /// A reporter for one of several concurrently running steps. Terminal output is
+    /// printed as it happens, tagged with `step`; log lines are held back until passed
+    /// to `replay`, so the log reads in plan order however the steps interleaved.
+    pub fn for_step(&self, step: &str) -> Reporter {
+        Reporter {
+            step: Some(step.to_string()),
+            ..Reporter::new(self.verbosity, self.format)
+        }
+    }
+
+    /// Record the log lines a step reporter collected, keeping their timestamps.
+    pub fn replay(&self, step: Reporter) {
+        if let Sink::Buffer(lines) = step.sink.into_inner().unwrap() {
+            for line in lines {
+                self.write_line(line);
+            }
+        }
+    }
+
+    /// Create the log file under `dir` and flush anything buffered so far.
+    ///
+    /// The executor calls this only after scaffolding, because most scaffolders refuse
+    /// to run in a non-empty directory.
+    pub fn open_in(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create log directory: {}", dir.display()))?;
+        let path = dir.join(format!(
+            "apply-{}.log",
+            Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+        ));
+        let mut file = File::create(&path)
+            .with_context(|| format!("Failed to create log file: {}", path.display()))?;
+
+        let mut sink = self.sink.lock().unwrap();
+        if let Sink::Buffer(lines) = &*sink {
+            for line in lines {
+                writeln!(file, "{}", line)?;
+            }
+        }
+        *sink = Sink::File(file);
+        *self.path.lock().unwrap() = Some(path.clone());
+        Ok(path)
+    }
+
+    /// Path of the log file, once opened.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.lock().unwrap().clone()
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Whether human-readable messages should be printed.
+    pub fn prints_text(&self) -> bool {
+        self.format == OutputFormat::Text && self.verbosity >= Verbosity::Normal
+    }
+
+    /// Record an event and report it on the terminal.
+    pub fn emit(&self, event: &Event) {
+        let text = event.describe();
+        match event {
+            Event::CommandOutput { step, stream, .. } => {
+                self.record(&format!("[{}] [{}] {}", step, stream.as_str(), text))
+            }
+            _ => self.record(&format!("[orchestrator] {}", text)),
+        }
+
+        match self.format {
+            OutputFormat::Json => {
+                let mut value = serde_json::to_value(event).unwrap_or_default();
+                if let Some(object) = value.as_object_mut() {
+                    if let Some(step) = &self.step {
+                        object.entry("step").or_insert_with(|| step.clone().into());
+                    }
+                    object.insert(
+                        "timestamp".to_string(),
+                        Utc::now()
+                            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                            .to_string()
+                            .into(),
+                    );
+                }
+                self.print(Output::Stdout(value.to_string()));
+            }
+            OutputFormat::Text => match event {
+                Event::CommandOutput { stream, line, .. } => {
+                    if self.verbosity >= Verbosity::Verbose {
+                        self.print(match stream {
+                            Stream::Stdout => Output::Stdout(line.to_string()),
+                            Stream::Stderr => Output::Stderr(line.to_string()),
+                        });
+                    }
+                }
+                Event::Warning { .. } | Event::MergeConflict { .. } => {
+                    if self.verbosity >= Verbosity::Normal {
+                        self.print(Output::Stderr(text));
+                    }
+                }
+                // Phase boundaries are noise on a terminal; the log keeps them
+                Event::StepStarted { argv: None, .. }
+                | Event::StepFinished { .. }
+                | Event::StepSkipped { .. }
+                | Event::Error { .. } => {}
+                _ => {
+                    if self.verbosity >= Verbosity::Normal {
+                        self.print(Output::Stdout(text));
+                    }
+                }
+            },
+        }
+    }
+
+    fn print(&self, output: Output) {
+        // Text from concurrent steps is told apart by its step; JSON events carry it
+        let tag = match (&self.step, self.format) {
+            (Some(step), OutputFormat::Text) => format!("[{}] ", step),
+            _ => String::new(),
+        };
+        // Lines from concurrent readers must not interleave
+        match output {
+            Output::Stdout(line) => {
+                let _ = writeln!(std::io::stdout().lock(), "{}{}", tag, line);
+            }
+            Output::Stderr(line) => {
+                let _ = writeln!(std::io::stderr().lock(), "{}{}", tag, line);
+            }
+        }
+    }
+
+    /// Run `f` as a named step, emitting `step_started` and `step_finished` around it.
+    pub fn step<T>(
+        &self,
+        step: &str,
+        argv: Option<&[String]>,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        self.emit(&Event::StepStarted { step, argv });
+        let started = Instant::now();
+        let result = f();
+        self.emit(&Event::StepFinished {
+            step,
+            duration_ms: started.elapsed().as_millis(),
+            success: result.is_ok(),
+        });
+        result
+    }
+
+    fn record(&self, line: &str) {
+        self.write_line(format!(
+            "{} {}",
+            Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+            line
+        ));
+    }
+
+    fn write_line(&self, stamped: String) {
+        let mut sink = self.sink.lock().unwrap();
+        match &mut *sink {
+            Sink::Buffer(lines) => lines.push(stamped),
+            // A failing log write must not abort the apply itself
+            Sink::File(file) => {
+                let _ = writeln!(file, "{}", stamped);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_buffered_lines_are_flushed() {
+        let dir = tempdir().unwrap();
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        reporter.emit(&Event::CommandOutput {
+            step: "scaffold",
+            stream: Stream::Stderr,
+            line: "early line",
+        });
+        let path = reporter.open_in(&dir.path().join(LOG_DIR)).unwrap();
+        reporter.emit(&Event::FileWritten {
+            path: "src/main.js",
+        });
+
+        let contents = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("[scaffold] [stderr] early line"));
+        assert!(lines[1].ends_with("[orchestrator] Wrote src/main.js"));
+    }
+
+    #[test]
+    fn test_replay_keeps_step_lines_together() {
+        let dir = tempdir().unwrap();
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        let first = reporter.for_step("a");
+        let second = reporter.for_step("b");
+        second.emit(&Event::FileWritten { path: "b" });
+        first.emit(&Event::FileWritten { path: "a1" });
+        first.emit(&Event::FileWritten { path: "a2" });
+        reporter.replay(first);
+        reporter.replay(second);
+
+        let path = reporter.open_in(&dir.path().join(LOG_DIR)).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        let written: Vec<&str> = contents
+            .lines()
+            .map(|l| l.rsplit(' ').next().unwrap())
+            .collect();
+        assert_eq!(written, vec!["a1", "a2", "b"]);
+    }
+
+    #[test]
+    fn test_event_json_shape() {
+        let argv = vec!["npm".to_string(), "install".to_string()];
+        let started = serde_json::to_value(Event::StepStarted {
+            step: "install",
+            argv: Some(&argv),
+        })
+        .unwrap();
+        assert_eq!(
+            started,
+            serde_json::json!({"event": "step_started", "step": "install", "argv": ["npm", "install"]})
+        );
+
+        let output = serde_json::to_value(Event::CommandOutput {
+            step: "install",
+            stream: Stream::Stderr,
+            line: "warn",
+        })
+        .unwrap();
+        assert_eq!(output["event"], "command_output");
+        assert_eq!(output["stream"], "stderr");
+    }
+}
@@ -1,9 +1,24 @@
 mod cli;
+mod detect;
 mod engine;
 mod executor;
+mod explain;
+mod js_ast;
+mod keyvalue;
+mod logging;
+mod manifest;
+mod merge;
+mod mutate;
 mod plan;
+mod policy;
+mod process;
 mod registry;
+mod revert;
+mod sandbox;
 mod spec;
+mod status;
+mod steps;
+mod wizard;
 
 fn main() {
     if let Err(e) = cli::Cli::run() {
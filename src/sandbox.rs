@@ -0,0 +1,201 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// Why a plan path could not be resolved inside the target directory.
+#[derive(Debug, Error)]
+pub enum SandboxError {
+    #[error("path '{0}' must be relative")]
+    AbsolutePath(String),
+    #[error("path '{0}' escapes the target directory")]
+    Escapes(String),
+    #[error(
+        "path '{path}' goes through symlink '{link}' which points outside the target directory"
+    )]
+    SymlinkEscape { path: String, link: PathBuf },
+    #[error("path '{path}' goes through dangling symlink '{link}'")]
+    DanglingSymlink { path: String, link: PathBuf },
+    #[error("failed to inspect '{path}': {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// Lexically normalize a plan path, removing `.` and resolving `..` without touching disk.
+///
+/// Fails for absolute paths, for paths that climb above the root and for paths that
+/// resolve to the root itself.
+pub fn normalize(path: &str) -> Result<PathBuf, SandboxError> {
+    let normalized = normalize_dir(path)?;
+    if normalized.as_os_str().is_empty() {
+        return Err(SandboxError::Escapes(path.to_string()));
+    }
+    Ok(normalized)
+}
+
+/// Like `normalize`, but the root itself (an empty result) is allowed.
+pub fn normalize_dir(path: &str) -> Result<PathBuf, SandboxError> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => {
+                return Err(SandboxError::AbsolutePath(path.to_string()))
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return Err(SandboxError::Escapes(path.to_string()));
+                }
+            }
+            Component::Normal(name) => normalized.push(name),
+        }
+    }
+    Ok(normalized)
+}
+
+/// Resolves plan paths against a root directory, refusing anything that lands outside it.
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    /// The root must already exist; it is canonicalized once up front.
+    pub fn new(root: &Path) -> Result<Self, SandboxError> {
+        let root = fs::canonicalize(root).map_err(|source| SandboxError::Io {
+            path: root.to_path_buf(),
+            source,
+        })?;
+        Ok(Sandbox { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve `path` to an absolute path inside the root.
+    ///
+    /// Every existing component is checked; symlinks are followed only while their
+    /// target stays inside the root, and dangling symlinks are refused because writing
+    /// through them would create a file wherever they point.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, SandboxError> {
+        let relative = normalize(path)?;
+        self.resolve_relative(path, &relative)
+    }
+
+    /// Resolve a directory inside the root; unlike `resolve`, the root itself is allowed.
+    pub fn resolve_dir(&self, path: &str) -> Result<PathBuf, SandboxError> {
+        let relative = normalize_dir(path)?;
+        self.resolve_relative(path, &relative)
+    }
+
+    fn resolve_relative(&self, path: &str, relative: &Path) -> Result<PathBuf, SandboxError> {
+        let mut resolved = self.root.clone();
+        let mut components = relative.components();
+
+        for component in components.by_ref() {
+            let candidate = resolved.join(component);
+            let metadata = match fs::symlink_metadata(&candidate) {
+                Ok(m) => m,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    // Nothing below here exists yet, so the rest cannot hop anywhere
+                    resolved = candidate;
+                    break;
+                }
+                Err(source) => {
+                    return Err(SandboxError::Io {
+                        path: candidate,
+                        source,
+                    })
+                }
+            };
+            if metadata.file_type().is_symlink() {
+                let target = match fs::canonicalize(&candidate) {
+                    Ok(t) => t,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        return Err(SandboxError::DanglingSymlink {
+                            path: path.to_string(),
+                            link: candidate,
+                        })
+                    }
+                    Err(source) => {
+                        return Err(SandboxError::Io {
+                            path: candidate,
+                            source,
+                        })
+                    }
+                };
+                if !target.starts_with(&self.root) {
+                    return Err(SandboxError::SymlinkEscape {
+                        path: path.to_string(),
+                        link: candidate,
+                    });
+                }
+                resolved = target;
+            } else {
+                resolved = candidate;
+            }
+        }
+        for component in components {
+            resolved.push(component);
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("./src/../index.js").unwrap(),
+            PathBuf::from("index.js")
+        );
+        assert!(matches!(normalize("../x"), Err(SandboxError::Escapes(_))));
+        assert!(matches!(normalize("a/.."), Err(SandboxError::Escapes(_))));
+        assert!(matches!(
+            normalize("/etc/passwd"),
+            Err(SandboxError::AbsolutePath(_))
+        ));
+        assert_eq!(normalize_dir("./").unwrap(), PathBuf::new());
+    }
+
+    #[test]
+    fn test_resolve_inside_root() {
+        let dir = tempdir().unwrap();
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        let path = sandbox.resolve("src/new/file.js").unwrap();
+        assert!(path.starts_with(sandbox.root()));
+        assert!(path.ends_with("src/new/file.js"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_refuses_symlink_hops() {
+        use std::os::unix::fs::symlink;
+
+        let outside = tempdir().unwrap();
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("inner")).unwrap();
+        symlink(outside.path(), dir.path().join("out")).unwrap();
+        symlink(dir.path().join("inner"), dir.path().join("alias")).unwrap();
+        symlink(outside.path().join("missing"), dir.path().join("dangling")).unwrap();
+
+        let sandbox = Sandbox::new(dir.path()).unwrap();
+        assert!(matches!(
+            sandbox.resolve("out/.bashrc"),
+            Err(SandboxError::SymlinkEscape { .. })
+        ));
+        assert!(matches!(
+            sandbox.resolve("dangling"),
+            Err(SandboxError::DanglingSymlink { .. })
+        ));
+        let path = sandbox.resolve("alias/file.txt").unwrap();
+        assert_eq!(path, sandbox.root().join("inner/file.txt"));
+    }
+}
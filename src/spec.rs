@@ -1,4 +1,9 @@
+use crate::plan::{CommandStep, GitAuthor, HookPoint};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The spec format version written by this build.
+pub const CURRENT_SPEC_VERSION: u32 = 1;
 
 /// The input from the user – declarative and versioned.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -7,6 +12,34 @@ pub struct ProjectSpec {
     pub language: String,
     pub framework: String,
     pub features: Vec<String>,
+    /// Extra commands to run at points of the apply lifecycle.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hooks: BTreeMap<HookPoint, Vec<CommandStep>>,
+    /// Put the generated project under version control.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitSpec>,
+}
+
+/// How the generated project is committed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitSpec {
+    /// Create a repository when the target is not already inside one.
+    #[serde(default = "default_init")]
+    pub init: bool,
+    /// Branch name for a new repository (git's default otherwise).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_branch: Option<String>,
+    /// Branch created when the target is inside an existing repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<GitAuthor>,
+}
+
+fn default_init() -> bool {
+    true
 }
 
 impl ProjectSpec {
@@ -16,4 +49,11 @@ impl ProjectSpec {
         let spec: ProjectSpec = serde_json::from_str(&contents)?;
         Ok(spec)
     }
+
+    /// Write to a JSON file (pretty-printed).
+    pub fn to_json_file(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,241 @@
+use crate::detect::declares;
+use crate::manifest::{generated_paths, sha256_hex, LockManifest};
+use crate::mutate::text_present;
+use crate::plan::{ExecutionPlan, FileModification};
+use crate::sandbox::Sandbox;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// One way a project differs from what its lock says orchestrator left behind.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Drift {
+    /// A generated file whose content no longer matches its recorded hash.
+    FileEdited { path: String },
+    /// A generated file with a recorded hash that is gone.
+    FileDeleted { path: String },
+    /// A file the plan generates that was never recorded and does not exist.
+    FileMissing { path: String },
+    /// A package the plan installs that `package.json` no longer declares.
+    DependencyRemoved { name: String, dev: bool },
+    /// A configuration change that can no longer be found in its file.
+    MutationMissing { path: String, mutation_type: String },
+}
+
+impl Drift {
+    pub fn describe(&self) -> String {
+        match self {
+            Drift::FileEdited { path } => format!("edited:      {}", path),
+            Drift::FileDeleted { path } => format!("deleted:     {}", path),
+            Drift::FileMissing { path } => format!("missing:     {}", path),
+            Drift::DependencyRemoved { name, dev: false } => {
+                format!("dependency:  {} removed from package.json", name)
+            }
+            Drift::DependencyRemoved { name, dev: true } => {
+                format!("dependency:  {} (dev) removed from package.json", name)
+            }
+            Drift::MutationMissing {
+                path,
+                mutation_type,
+            } => {
+                format!(
+                    "mutation:    {} to {} no longer present",
+                    mutation_type, path
+                )
+            }
+        }
+    }
+}
+
+/// Compare a project against its lock and the full plan it was last brought to.
+pub fn check(project_dir: &Path, lock: &LockManifest, plan: &ExecutionPlan) -> Result<Vec<Drift>> {
+    let sandbox = Sandbox::new(project_dir)?;
+    let mut drift = Vec::new();
+
+    for (path, sha256) in &lock.files {
+        match std::fs::read(sandbox.resolve(path)?) {
+            Ok(bytes) if sha256_hex(&bytes) != *sha256 => {
+                drift.push(Drift::FileEdited { path: path.clone() })
+            }
+            Ok(_) => {}
+            Err(_) => drift.push(Drift::FileDeleted { path: path.clone() }),
+        }
+    }
+    let mut unrecorded: Vec<String> = generated_paths(plan)
+        .into_iter()
+        .filter(|path| !lock.files.contains_key(path))
+        .collect();
+    unrecorded.sort_unstable();
+    unrecorded.dedup();
+    for path in unrecorded {
+        if !sandbox.resolve(&path)?.exists() {
+            drift.push(Drift::FileMissing { path });
+        }
+    }
+
+    let package_path = sandbox.resolve("package.json")?;
+    let package: Value = match std::fs::read_to_string(&package_path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", package_path.display()))?,
+        Err(_) => Value::Null,
+    };
+    let installed = plan
+        .dependencies
+        .iter()
+        .map(|d| (d, false))
+        .chain(plan.dev_dependencies.iter().map(|d| (d, true)));
+    for (dep, dev) in installed {
+        if !declares(&package, &dep.name) {
+            drift.push(Drift::DependencyRemoved {
+                name: dep.name.clone(),
+                dev,
+            });
+        }
+    }
+
+    for modification in &plan.file_modifications {
+        let contents = std::fs::read_to_string(sandbox.resolve(&modification.path)?).ok();
+        if mutation_present(contents.as_deref(), modification) == Some(false) {
+            drift.push(Drift::MutationMissing {
+                path: modification.path.clone(),
+                mutation_type: modification.mutation_type.clone(),
+            });
+        }
+    }
+
+    Ok(drift)
+}
+
+/// Whether the effect of `modification` can still be found in `contents`, or `None` for
+/// mutation types whose effect cannot be checked after the fact.
+fn mutation_present(contents: Option<&str>, modification: &FileModification) -> Option<bool> {
+    let contents = match contents {
+        Some(contents) => contents,
+        None => return Some(false),
+    };
+    match modification.mutation_type.as_str() {
+        "text_append" => Some(contents.contains(&modification.content)),
+        "json_merge" => {
+            let patch: Value = serde_json::from_str(&modification.content).ok()?;
+            let current: Value = match serde_json::from_str(contents) {
+                Ok(current) => current,
+                Err(_) => return Some(false),
+            };
+            Some(json_contains(&current, &patch))
+        }
+        other => text_present(other, &modification.path, contents, &modification.content),
+    }
+}
+
+/// Whether every key of `patch` is in `current` with the same value, recursing into objects.
+fn json_contains(current: &Value, patch: &Value) -> bool {
+    match (current, patch) {
+        (Value::Object(current), Value::Object(patch)) => patch
+            .iter()
+            .all(|(key, value)| current.get(key).is_some_and(|c| json_contains(c, value))),
+        _ => current == patch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::mutate::apply_text;
+    use crate::registry::sqlite::SqliteRegistry;
+    use crate::spec::ProjectSpec;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_status_reports_drift() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+        let spec = ProjectSpec {
+            spec_version: 1,
+            language: "javascript".to_string(),
+            framework: "react".to_string(),
+            features: vec!["tailwind".to_string()],
+            hooks: Default::default(),
+            git: None,
+        };
+        let plan = Engine::resolve(&spec, &registry).unwrap();
+        let project = dir.path().join("app");
+        fs::create_dir(&project).unwrap();
+        fs::write(
+            project.join("package.json"),
+            r#"{"dependencies": {"react": "^18.0.0"}, "devDependencies": {}}"#,
+        )
+        .unwrap();
+        let mut config = "export default defineConfig({\n  plugins: [react()],\n})\n".to_string();
+        for m in &plan.file_modifications {
+            config = apply_text(&m.mutation_type, &m.path, &config, &m.content)
+                .unwrap()
+                .unwrap_or(config);
+        }
+        fs::write(project.join("vite.config.js"), config).unwrap();
+
+        let mut lock = LockManifest::new(spec, plan.clone(), vec![]);
+        lock.record_files(&project, generated_paths(&plan).iter().map(String::as_str))
+            .unwrap();
+        assert!(check(&project, &lock, &plan)
+            .unwrap()
+            .iter()
+            .all(|d| matches!(d, Drift::DependencyRemoved { dev: true, .. })));
+
+        let edited = &plan.file_modifications[0].path;
+        fs::write(project.join(edited), "// rewritten\n").unwrap();
+        let drift = check(&project, &lock, &plan).unwrap();
+        assert!(drift.contains(&Drift::FileEdited {
+            path: edited.clone()
+        }));
+        assert!(drift.contains(&Drift::DependencyRemoved {
+            name: "tailwindcss".to_string(),
+            dev: true,
+        }));
+        assert!(drift
+            .iter()
+            .any(|d| matches!(d, Drift::MutationMissing { path, .. } if path == edited)));
+    }
+
+    #[test]
+    fn test_status_checks_files_of_version_1_plans() {
+        let dir = tempdir().unwrap();
+        let plan: ExecutionPlan = serde_json::from_value(serde_json::json!({
+            "plan_version": 1,
+            "scaffold": null,
+            "dependencies": [],
+            "dev_dependencies": [],
+            "file_writes": [
+                {"path": "README.md", "content": "hi\n"},
+                {"path": "LICENSE", "content": "MIT\n"}
+            ],
+            "file_modifications": [],
+            "scripts": []
+        }))
+        .unwrap();
+        fs::write(dir.path().join("README.md"), "hi\n").unwrap();
+
+        let mut lock = LockManifest::for_plan(plan.clone(), vec![]);
+        lock.record_files(
+            dir.path(),
+            generated_paths(&plan).iter().map(String::as_str),
+        )
+        .unwrap();
+        assert_eq!(lock.files.len(), 1);
+        fs::write(dir.path().join("README.md"), "edited\n").unwrap();
+        assert_eq!(
+            check(dir.path(), &lock, &plan).unwrap(),
+            vec![
+                Drift::FileEdited {
+                    path: "README.md".to_string()
+                },
+                Drift::FileMissing {
+                    path: "LICENSE".to_string()
+                },
+            ]
+        );
+    }
+}
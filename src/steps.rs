@@ -0,0 +1,250 @@
+use crate::plan::{ExecutionPlan, Phase, RepoCondition, Step, StepAction};
+use std::collections::BTreeMap;
+
+/// A step contributed by the registry (or another source) at a given phase.
+#[derive(Debug, Clone)]
+pub struct ContributedStep {
+    pub id: String,
+    pub phase: Phase,
+    pub when: Option<RepoCondition>,
+    pub action: StepAction,
+}
+
+/// Build the ordered step list for a plan from its fixed-phase fields plus any
+/// contributed steps.
+///
+/// Ordering edges:
+/// - every step depends on all steps of the previous non-empty phase;
+/// - within a phase, commands run one after another (package managers do not like
+///   sharing a directory), and file steps touching the same path keep their order.
+pub fn assemble(plan: &ExecutionPlan, contributed: Vec<ContributedStep>) -> Vec<Step> {
+    let mut phases: BTreeMap<Phase, Vec<(String, Option<RepoCondition>, StepAction)>> =
+        BTreeMap::new();
+
+    if let Some(scaffold) = &plan.scaffold {
+        phases.entry(Phase::Scaffold).or_default().push((
+            "scaffold".to_string(),
+            None,
+            StepAction::RunCommand(scaffold.clone()),
+        ));
+    }
+    if !plan.dependencies.is_empty() {
+        phases.entry(Phase::Install).or_default().push((
+            "install".to_string(),
+            None,
+            StepAction::InstallPackages {
+                packages: plan.dependencies.clone(),
+                dev: false,
+            },
+        ));
+    }
+    if !plan.dev_dependencies.is_empty() {
+        phases.entry(Phase::Install).or_default().push((
+            "install-dev".to_string(),
+            None,
+            StepAction::InstallPackages {
+                packages: plan.dev_dependencies.clone(),
+                dev: true,
+            },
+        ));
+    }
+    for file_write in &plan.file_writes {
+        phases.entry(Phase::Files).or_default().push((
+            format!("write:{}", file_write.path),
+            None,
+            StepAction::WriteFile {
+                path: file_write.path.clone(),
+                content: file_write.content.clone(),
+            },
+        ));
+    }
+    let mut per_path: BTreeMap<&str, usize> = BTreeMap::new();
+    for modification in &plan.file_modifications {
+        let n = per_path.entry(modification.path.as_str()).or_default();
+        *n += 1;
+        phases.entry(Phase::Modify).or_default().push((
+            format!("modify:{}#{}", modification.path, n),
+            None,
+            StepAction::ModifyFile(modification.clone()),
+        ));
+    }
+    for step in contributed {
+        phases
+            .entry(step.phase)
+            .or_default()
+            .push((step.id, step.when, step.action));
+    }
+
+    let mut steps = Vec::new();
+    let mut previous_phase: Vec<String> = Vec::new();
+    for (phase, entries) in phases {
+        let mut this_phase = Vec::new();
+        let mut last_command: Option<String> = None;
+        let mut earlier_paths: Vec<(String, String)> = Vec::new();
+
+        for (id, when, action) in entries {
+            let mut depends_on = previous_phase.clone();
+            match touched_path(&action) {
+                Some(path) => {
+                    // A directory step and the files inside it must not run concurrently
+                    for (earlier, previous) in &earlier_paths {
+                        if overlaps(earlier, path) && !depends_on.contains(previous) {
+                            depends_on.push(previous.clone());
+                        }
+                    }
+                    earlier_paths.push((path.to_string(), id.clone()));
+                }
+                None => {
+                    if let Some(previous) = last_command.replace(id.clone()) {
+                        depends_on.push(previous);
+                    }
+                }
+            }
+            this_phase.push(id.clone());
+            steps.push(Step {
+                id,
+                phase,
+                depends_on,
+                when,
+                action,
+            });
+        }
+        previous_phase = this_phase;
+    }
+    steps
+}
+
+/// The file a step works on, or `None` for command-like steps.
+pub fn touched_path(action: &StepAction) -> Option<&str> {
+    match action {
+        StepAction::WriteFile { path, .. }
+        | StepAction::CreateDir { path }
+        | StepAction::Delete { path }
+        | StepAction::Chmod { path, .. } => Some(path),
+        StepAction::GitIgnore { .. } => Some(".gitignore"),
+        StepAction::ModifyFile(modification) => Some(&modification.path),
+        StepAction::RunCommand(_)
+        | StepAction::InstallPackages { .. }
+        | StepAction::GitInit { .. }
+        | StepAction::GitCommit { .. } => None,
+    }
+}
+
+/// Whether two paths are the same or one lies inside the other.
+fn overlaps(a: &str, b: &str) -> bool {
+    let trim = |path: &str| {
+        path.trim_start_matches("./")
+            .trim_end_matches('/')
+            .to_string()
+    };
+    let (a, b) = (trim(a), trim(b));
+    a == b || b.starts_with(&format!("{}/", a)) || a.starts_with(&format!("{}/", b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::*;
+
+    #[test]
+    fn test_assemble_orders_phases_and_chains() {
+        let plan = ExecutionPlan {
+            plan_version: 1,
+            steps: vec![],
+            scaffold: Some(CommandStep {
+                command: "npm".to_string(),
+                args: vec!["create".to_string(), "vite@latest".to_string()],
+                env: Default::default(),
+                cwd: None,
+                timeout_secs: None,
+                creates: Vec::new(),
+            }),
+            dependencies: vec![DependencyInstall {
+                name: "react-router-dom".to_string(),
+                version: "^6.0".to_string(),
+            }],
+            dev_dependencies: vec![DependencyInstall {
+                name: "tailwindcss".to_string(),
+                version: "^3.0".to_string(),
+            }],
+            file_writes: vec![],
+            file_modifications: vec![
+                FileModification {
+                    path: "vite.config.js".to_string(),
+                    mutation_type: "text_append".to_string(),
+                    content: "a".to_string(),
+                },
+                FileModification {
+                    path: "vite.config.js".to_string(),
+                    mutation_type: "text_append".to_string(),
+                    content: "b".to_string(),
+                },
+            ],
+            scripts: vec![],
+            provenance: None,
+            registry_version: None,
+            plan_hash: None,
+        };
+        let contributed = vec![
+            ContributedStep {
+                id: "tailwind-init".to_string(),
+                phase: Phase::PostInstall,
+                when: None,
+                action: StepAction::CreateDir {
+                    path: "src/styles".to_string(),
+                },
+            },
+            ContributedStep {
+                id: "styles-entry".to_string(),
+                phase: Phase::PostInstall,
+                when: None,
+                action: StepAction::WriteFile {
+                    path: "src/styles/index.css".to_string(),
+                    content: String::new(),
+                },
+            },
+            ContributedStep {
+                id: "src-readme".to_string(),
+                phase: Phase::PostInstall,
+                when: None,
+                action: StepAction::WriteFile {
+                    path: "src-readme.md".to_string(),
+                    content: String::new(),
+                },
+            },
+        ];
+
+        let steps = assemble(&plan, contributed);
+        let ids: Vec<&str> = steps.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "scaffold",
+                "install",
+                "install-dev",
+                "tailwind-init",
+                "styles-entry",
+                "src-readme",
+                "modify:vite.config.js#1",
+                "modify:vite.config.js#2"
+            ]
+        );
+        assert_eq!(steps[2].depends_on, vec!["scaffold", "install"]);
+        assert_eq!(steps[3].depends_on, vec!["install", "install-dev"]);
+        // Files inside a directory wait for it; a mere name prefix does not
+        assert_eq!(
+            steps[4].depends_on,
+            vec!["install", "install-dev", "tailwind-init"]
+        );
+        assert_eq!(steps[5].depends_on, vec!["install", "install-dev"]);
+        assert_eq!(
+            steps[7].depends_on,
+            vec![
+                "tailwind-init",
+                "styles-entry",
+                "src-readme",
+                "modify:vite.config.js#1"
+            ]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::registry::models::Feature;
+use crate::registry::Registry;
+use crate::spec::{ProjectSpec, CURRENT_SPEC_VERSION};
+use anyhow::{bail, Result};
+use std::io::{BufRead, Write};
+
+/// Answers supplied up front (e.g. from CLI flags).
+/// Anything left as `None` is prompted for when `interactive` is set.
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    pub language: Option<String>,
+    pub framework: Option<String>,
+    pub features: Option<Vec<String>>,
+    pub interactive: bool,
+}
+
+/// Builds a `ProjectSpec` by querying the registry, validating every answer as it goes.
+pub struct Wizard;
+
+impl Wizard {
+    pub fn run(
+        registry: &dyn Registry,
+        opts: &InitOptions,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<ProjectSpec> {
+        let frameworks = registry.list_frameworks()?;
+        if frameworks.is_empty() {
+            bail!("The registry does not contain any frameworks");
+        }
+
+        // 1. Language
+        let mut languages: Vec<String> = frameworks.iter().map(|f| f.language.clone()).collect();
+        languages.sort();
+        languages.dedup();
+        let language = match &opts.language {
+            Some(lang) => {
+                if !languages.contains(lang) {
+                    bail!(
+                        "Unknown language '{}' (available: {})",
+                        lang,
+                        languages.join(", ")
+                    );
+                }
+                lang.clone()
+            }
+            None if opts.interactive => {
+                let choices: Vec<(String, Option<String>)> =
+                    languages.iter().map(|l| (l.clone(), None)).collect();
+                prompt_choice(input, output, "Language", &choices)?
+            }
+            None => bail!("--language is required in non-interactive mode"),
+        };
+
+        // 2. Framework
+        let framework = match &opts.framework {
+            Some(fw) => {
+                if !registry.framework_supports_language(fw, &language)? {
+                    bail!(
+                        "Framework '{}' does not support language '{}'",
+                        fw,
+                        language
+                    );
+                }
+                fw.clone()
+            }
+            None if opts.interactive => {
+                let choices: Vec<(String, Option<String>)> = frameworks
+                    .iter()
+                    .filter(|f| f.language == language)
+                    .map(|f| (f.id.clone(), f.base_scaffold_command.clone()))
+                    .collect();
+                prompt_choice(input, output, "Framework", &choices)?
+            }
+            None => bail!("--framework is required in non-interactive mode"),
+        };
+
+        // 3. Features
+        let available = registry.features_for_framework(&framework)?;
+        let mut features = match &opts.features {
+            Some(requested) => {
+                let mut selected: Vec<String> = Vec::new();
+                for feature in requested {
+                    if !registry.is_feature_compatible(&framework, feature)? {
+                        bail!(
+                            "Feature '{}' is not compatible with framework '{}'",
+                            feature,
+                            framework
+                        );
+                    }
+                    if !selected.contains(feature) {
+                        selected.push(feature.clone());
+                    }
+                }
+                selected
+            }
+            None if opts.interactive => prompt_features(input, output, &available)?,
+            None => vec![],
+        };
+
+        // Required features are always part of the framework.
+        for feature in available.iter().filter(|f| f.required) {
+            if !features.contains(&feature.id) {
+                features.push(feature.id.clone());
+            }
+        }
+
+        Ok(ProjectSpec {
+            spec_version: CURRENT_SPEC_VERSION,
+            language,
+            framework,
+            features,
+            hooks: Default::default(),
+            git: None,
+        })
+    }
+}
+
+/// Prompt until the user picks one of `choices`, by number or by id.
+fn prompt_choice(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    label: &str,
+    choices: &[(String, Option<String>)],
+) -> Result<String> {
+    if choices.is_empty() {
+        bail!("No {} available", label.to_lowercase());
+    }
+    writeln!(output, "{}:", label)?;
+    for (i, (id, description)) in choices.iter().enumerate() {
+        match description {
+            Some(d) => writeln!(output, "  {}) {} - {}", i + 1, id, d)?,
+            None => writeln!(output, "  {}) {}", i + 1, id)?,
+        }
+    }
+    loop {
+        write!(output, "{} [1-{}]: ", label, choices.len())?;
+        output.flush()?;
+        let answer = read_line(input)?;
+        if let Some(id) = match_choice(&answer, choices.iter().map(|(id, _)| id.as_str())) {
+            return Ok(id);
+        }
+        writeln!(output, "Invalid choice '{}'", answer)?;
+    }
+}
+
+/// Prompt for a comma-separated list of features. Required features are listed but not asked for.
+fn prompt_features(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    available: &[Feature],
+) -> Result<Vec<String>> {
+    let optional: Vec<&Feature> = available.iter().filter(|f| !f.required).collect();
+    for feature in available.iter().filter(|f| f.required) {
+        writeln!(output, "Including required feature '{}'", feature.id)?;
+    }
+    if optional.is_empty() {
+        return Ok(vec![]);
+    }
+    writeln!(output, "Features:")?;
+    for (i, feature) in optional.iter().enumerate() {
+        match &feature.description {
+            Some(d) => writeln!(output, "  {}) {} - {}", i + 1, feature.id, d)?,
+            None => writeln!(output, "  {}) {}", i + 1, feature.id)?,
+        }
+    }
+    'prompt: loop {
+        write!(output, "Features (comma-separated, empty for none): ")?;
+        output.flush()?;
+        let answer = read_line(input)?;
+        let mut selected = Vec::new();
+        for part in answer.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match match_choice(part, optional.iter().map(|f| f.id.as_str())) {
+                Some(id) if !selected.contains(&id) => selected.push(id),
+                Some(_) => {}
+                None => {
+                    writeln!(output, "Unknown feature '{}'", part)?;
+                    continue 'prompt;
+                }
+            }
+        }
+        return Ok(selected);
+    }
+}
+
+/// Resolve an answer that is either a 1-based index or an id.
+fn match_choice<'a>(answer: &str, ids: impl Iterator<Item = &'a str>) -> Option<String> {
+    let ids: Vec<&str> = ids.collect();
+    if let Ok(n) = answer.parse::<usize>() {
+        return ids.get(n.checked_sub(1)?).map(|s| s.to_string());
+    }
+    ids.into_iter()
+        .find(|id| *id == answer)
+        .map(|s| s.to_string())
+}
+
+fn read_line(input: &mut dyn BufRead) -> Result<String> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        bail!("Unexpected end of input");
+    }
+    Ok(line.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::sqlite::SqliteRegistry;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_interactive_wizard() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let opts = InitOptions {
+            interactive: true,
+            ..Default::default()
+        };
+        // language by id, framework by number (only react for javascript), an invalid then valid feature list
+        let mut input = "javascript\n1\nbogus\neslint, 3\n".as_bytes();
+        let mut output = Vec::new();
+        let spec = Wizard::run(&registry, &opts, &mut input, &mut output).unwrap();
+
+        assert_eq!(spec.language, "javascript");
+        assert_eq!(spec.framework, "react");
+        assert_eq!(spec.features, vec!["eslint", "tailwind"]);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("Unknown feature 'bogus'"));
+    }
+
+    #[test]
+    fn test_non_interactive_adds_required_features() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let opts = InitOptions {
+            language: Some("typescript".to_string()),
+            framework: Some("react-ts".to_string()),
+            features: Some(vec!["router".to_string()]),
+            interactive: false,
+        };
+        let spec = Wizard::run(&registry, &opts, &mut "".as_bytes(), &mut Vec::new()).unwrap();
+        assert_eq!(spec.features, vec!["router", "typescript"]);
+    }
+
+    #[test]
+    fn test_non_interactive_rejects_incompatible_framework() {
+        let dir = tempdir().unwrap();
+        let registry = SqliteRegistry::new(&dir.path().join("test.db")).unwrap();
+
+        let opts = InitOptions {
+            language: Some("javascript".to_string()),
+            framework: Some("react-ts".to_string()),
+            features: None,
+            interactive: false,
+        };
+        let result = Wizard::run(&registry, &opts, &mut "".as_bytes(), &mut Vec::new());
+        assert!(result.is_err());
+    }
+}